@@ -1,30 +1,252 @@
-///! A builder interface for the logger.
-use log::LevelFilter;
+//! A builder interface for the logger.
+
+use log::{Level, LevelFilter};
 use std::collections::HashSet;
 use std::error::Error;
 use std::fmt::Display;
+use std::fs::File;
 use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicIsize, AtomicU64, Ordering};
 use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use time::error::InvalidFormatDescription;
+use time::format_description::{FormatItem, OwnedFormatItem};
 
-use crate::logger::Logger;
+use crate::early;
+use crate::env::{EnvConfig, EnvTarget};
+use crate::logger::{
+    ansi_level_tags, level_tag, log_facade_is_nih_log, ErrorContextRing, FilterFn, FlushThread,
+    GlobalLoggerProxy, Logger, RecordHook, RouteLevels,
+};
 use crate::target::OutputTargetImpl;
+use crate::theme::{Color, Theme};
 use crate::LOGGER_INSTANCE;
 
+/// The maximum log level selected through the `level-off`, `level-error`, `level-warn`,
+/// `level-info`, `level-debug`, and `level-trace` cargo features, or `None` if none of those
+/// features are enabled. If multiple features are enabled, then the most verbose level is used.
+/// This is used by [`LoggerBuilder::from_features()`].
+pub const FEATURE_MAX_LEVEL: Option<LevelFilter> = if cfg!(feature = "level-trace") {
+    Some(LevelFilter::Trace)
+} else if cfg!(feature = "level-debug") {
+    Some(LevelFilter::Debug)
+} else if cfg!(feature = "level-info") {
+    Some(LevelFilter::Info)
+} else if cfg!(feature = "level-warn") {
+    Some(LevelFilter::Warn)
+} else if cfg!(feature = "level-error") {
+    Some(LevelFilter::Error)
+} else if cfg!(feature = "level-off") {
+    Some(LevelFilter::Off)
+} else {
+    None
+};
+
 /// Constructs an NIH-log logger.
-#[derive(Debug)]
 pub struct LoggerBuilder {
     /// The maximum log level. Set when constructing the builder.
     max_log_level: LevelFilter,
     /// If set to `true`, then the module path is always shown. Useful for debug builds and to
     /// configure the module blacklist.
     always_show_module_path: bool,
+    /// If set to `true`, then source locations are written as clickable OSC 8 hyperlinks when the
+    /// output target supports colors.
+    clickable_locations: bool,
+    /// How the current thread is shown for debug and trace messages.
+    thread_format: ThreadFormat,
+    /// If set to `true`, then the operating system's thread ID is shown instead of Rust's thread
+    /// ID.
+    os_thread_id: bool,
+    /// If set to `true`, then the name of the main thread is shown like any other thread name.
+    show_main_thread_name: bool,
+    /// If set to `true`, then no thread information is shown for threads without a name.
+    hide_unnamed_threads: bool,
+    /// If set to `true`, then only the log message itself is written, without a timestamp, log
+    /// level, or any other context.
+    message_only: bool,
+    /// If set to `true`, then info records are written like in `message_only` mode while the
+    /// other levels keep their usual formatting.
+    plain_info: bool,
+    /// If set to `true`, then records are written as `key=value` pairs in the logfmt format.
+    logfmt: bool,
+    /// A fixed string written at the start of every line, before the timestamp.
+    line_prefix: Option<String>,
+    /// The separator written between the columns of a log line.
+    column_separator: String,
+    /// If set to `true`, then the continuation lines of multi-line messages are indented to line
+    /// up with the message's first line.
+    multiline_indent: bool,
+    /// If set, then messages are hard-wrapped so lines don't exceed this many columns.
+    wrap_width: Option<usize>,
+    /// If set to `true`, then a single trailing newline is removed from messages so it isn't
+    /// doubled.
+    normalize_newlines: bool,
+    /// The capacity reserved for the thread-local message scratch buffer.
+    line_capacity: usize,
+    /// If set to `true`, then every record is prefixed with a monotonically increasing sequence
+    /// number.
+    sequence_numbers: bool,
+    /// If set to `true`, then the time since the previous record is shown after the timestamp.
+    interline_delta: bool,
+    /// A custom format for the timestamp at the start of every line. The default
+    /// `[hour]:[minute]:[second]` format is used if this is not set.
+    time_format: Option<OwnedFormatItem>,
+    /// If set to `true`, then the timestamp at the start of every line is replaced by the system's
+    /// monotonic clock.
+    monotonic_timestamps: bool,
+    /// If set to `false`, then no timestamp is written at the start of every line.
+    timestamps: bool,
+    /// Records at or above this level are followed by a backtrace.
+    backtrace_level: LevelFilter,
+    /// If set, then this many filtered out records are kept in memory and written before the next
+    /// error record.
+    error_context_lines: Option<usize>,
+    /// Whether the records captured since [`init_early()`][crate::init_early()] should be written
+    /// when the logger is installed. They're discarded otherwise.
+    capture_early: bool,
     /// An explicitly set output target. If this is not set then the target is chosen based on the
     /// presence and contents of the `NIH_LOG` environment variable.
     output_target: Option<OutputTargetImpl>,
+    /// The target used instead of the dynamic STDERR or WinDbg target when neither `output_target`
+    /// nor the `NIH_LOG` environment variable specify a target.
+    default_target: Option<OutputTarget>,
+    /// Additional output targets for records at certain levels, added through
+    /// [`route_level()`][Self::route_level()] and [`categorized_files()`][Self::categorized_files()].
+    level_routes: Vec<(RouteLevels, OutputTargetImpl)>,
+    /// Overrides the output target's default color behavior.
+    color_mode: ColorMode,
+    /// The colors used when the output target supports colors.
+    theme: Theme,
+    /// The tags written for each log level, indexed by `level as usize - 1`.
+    level_labels: [String; 5],
+    /// If set to `true`, then the level tags are padded to the width of the longest tag.
+    align_level_tags: bool,
+    /// If set to `true`, then writes to STDERR and files are not buffered.
+    unbuffered: bool,
+    /// The size of the write buffer for files and raw STDERR streams, if it should be changed.
+    buffer_capacity: Option<usize>,
+    /// If set, then a background thread flushes the logger at this interval.
+    flush_interval: Option<Duration>,
+    /// If set, then targets are only flushed after a record once this many bytes are unflushed.
+    flush_threshold: Option<u64>,
     /// Names of crates module paths that should be excluded from the log. Case sensitive, and only
     /// matches whole crate names and paths. Both the crate name and module path are checked
     /// separately to allow for a little bit of flexibility.
     module_blacklist: HashSet<String>,
+    /// Names of crates and module paths whose module paths should not be shown. Uses the same
+    /// matching rules as `module_blacklist`.
+    hidden_module_paths: HashSet<String>,
+    /// Names of crates and modules whose messages are highlighted.
+    highlighted_crates: HashSet<String>,
+    /// If set to `true`, then the record's target is shown alongside the module path when it
+    /// differs from the module path.
+    show_target: bool,
+    /// An optional user provided filter function. Records for which this function returns `false`
+    /// are not logged.
+    filter_fn: Option<FilterFn>,
+    /// An optional user provided function that's called for every record that passes the filters.
+    record_hook: Option<RecordHook>,
+    /// Another logger that every record is also forwarded to.
+    chained_logger: Option<Box<dyn log::Log>>,
+    /// If set to `true`, then a session header is written when logging to a file.
+    session_header: bool,
+    /// Additional `key=value` pairs written as part of the session header.
+    header_fields: Vec<(String, String)>,
+    /// If set to `true`, then a session footer is written to file targets on
+    /// [`shutdown()`][crate::shutdown()].
+    session_footer: bool,
+    /// If set to `true`, then a UTF-8 byte order mark is written to newly created log files.
+    utf8_bom: bool,
+    /// What to do when the local time offset cannot be determined.
+    offset_failure_action: OffsetFailureAction,
+    /// If set to `true`, then the maximum log level is capped at [`LevelFilter::Warn`].
+    quiet: bool,
+    /// If set to `true`, then a line is written when the effective log level changes at runtime.
+    level_change_markers: bool,
+    /// The signals that increase and decrease the log level's verbosity at runtime, if set. Only
+    /// used on Unix.
+    signal_control: Option<(i32, i32)>,
+    /// Whether reentrant logging calls should be detected.
+    reentrancy_guard: bool,
+    /// If set to `true`, then the parent directories of log files are created if they don't exist.
+    create_log_dirs: bool,
+    /// If set to `true`, then [`prepare_for_fork()`][crate::prepare_for_fork()] also closes log
+    /// files.
+    close_files_before_fork: bool,
+    /// If set, then a marker is written to this target the first time a reentrant logging call
+    /// is detected.
+    reentrancy_report: Option<OutputTarget>,
+}
+
+impl std::fmt::Debug for LoggerBuilder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LoggerBuilder")
+            .field("max_log_level", &self.max_log_level)
+            .field("always_show_module_path", &self.always_show_module_path)
+            .field("clickable_locations", &self.clickable_locations)
+            .field("thread_format", &self.thread_format)
+            .field("os_thread_id", &self.os_thread_id)
+            .field("show_main_thread_name", &self.show_main_thread_name)
+            .field("hide_unnamed_threads", &self.hide_unnamed_threads)
+            .field("message_only", &self.message_only)
+            .field("plain_info", &self.plain_info)
+            .field("logfmt", &self.logfmt)
+            .field("line_prefix", &self.line_prefix)
+            .field("column_separator", &self.column_separator)
+            .field("multiline_indent", &self.multiline_indent)
+            .field("wrap_width", &self.wrap_width)
+            .field("normalize_newlines", &self.normalize_newlines)
+            .field("line_capacity", &self.line_capacity)
+            .field("sequence_numbers", &self.sequence_numbers)
+            .field("interline_delta", &self.interline_delta)
+            .field("time_format", &self.time_format)
+            .field("monotonic_timestamps", &self.monotonic_timestamps)
+            .field("timestamps", &self.timestamps)
+            .field("backtrace_level", &self.backtrace_level)
+            .field("error_context_lines", &self.error_context_lines)
+            .field("capture_early", &self.capture_early)
+            .field("output_target", &self.output_target)
+            .field("default_target", &self.default_target)
+            .field("level_routes", &self.level_routes)
+            .field("color_mode", &self.color_mode)
+            .field("theme", &self.theme)
+            .field("level_labels", &self.level_labels)
+            .field("align_level_tags", &self.align_level_tags)
+            .field("unbuffered", &self.unbuffered)
+            .field("buffer_capacity", &self.buffer_capacity)
+            .field("flush_interval", &self.flush_interval)
+            .field("flush_threshold", &self.flush_threshold)
+            .field("module_blacklist", &self.module_blacklist)
+            .field("hidden_module_paths", &self.hidden_module_paths)
+            .field("highlighted_crates", &self.highlighted_crates)
+            .field("show_target", &self.show_target)
+            .field(
+                "filter_fn",
+                &self.filter_fn.as_ref().map(|_| "<filter function>"),
+            )
+            .field(
+                "record_hook",
+                &self.record_hook.as_ref().map(|_| "<record hook>"),
+            )
+            .field(
+                "chained_logger",
+                &self.chained_logger.as_ref().map(|_| "<chained logger>"),
+            )
+            .field("session_header", &self.session_header)
+            .field("header_fields", &self.header_fields)
+            .field("session_footer", &self.session_footer)
+            .field("utf8_bom", &self.utf8_bom)
+            .field("offset_failure_action", &self.offset_failure_action)
+            .field("quiet", &self.quiet)
+            .field("level_change_markers", &self.level_change_markers)
+            .field("signal_control", &self.signal_control)
+            .field("reentrancy_guard", &self.reentrancy_guard)
+            .field("create_log_dirs", &self.create_log_dirs)
+            .field("close_files_before_fork", &self.close_files_before_fork)
+            .field("reentrancy_report", &self.reentrancy_report)
+            .finish()
+    }
 }
 
 /// Determines where the logger should write its output. If no explicit target is chosen, then a
@@ -33,11 +255,52 @@ pub struct LoggerBuilder {
 pub enum OutputTarget {
     /// Write directly to STDERR.
     Stderr,
+    /// Write to STDERR through [`std::io::stderr()`] instead of through `termcolor`. This never
+    /// uses colors and has less overhead per write. Records are still written using a single write
+    /// when possible. Installing the logger fails if this is combined with [`ColorMode::Always`].
+    RawStderr,
     /// Output to the Windows debugger using `OutputDebugString()`.
     #[cfg(windows)]
     WinDbg,
     /// Write the log output to a file.
     File(PathBuf),
+    /// Write the log output to a file, but truncate the file instead of letting it grow beyond
+    /// `max_bytes`. The file is truncated before writing a new line, so it can temporarily grow
+    /// beyond `max_bytes` by up to one record. This is a simpler alternative to rotating logs for
+    /// devices with limited storage.
+    CappedFile { path: PathBuf, max_bytes: u64 },
+    /// Write the log output to a temporary file next to the path, and rename the temporary file to
+    /// the path when [`shutdown()`][crate::shutdown()] is called. Readers thus never see a
+    /// partially written log. This suits applications that produce one self-contained log per
+    /// run. An existing file at the path is replaced. The temporary file uses the same name with
+    /// `.tmp` appended. If the process exits without calling `shutdown()`, then the log stays in
+    /// the temporary file. If the target is discarded before that, for instance because installing
+    /// the logger failed or because it was a thread target that was replaced, then the temporary
+    /// file is removed and the existing file is left untouched. Records logged after `shutdown()`
+    /// are appended to the published file.
+    AtomicFile(PathBuf),
+    /// Write gzip compressed log output to a file. This requires the `flate2` feature. Since
+    /// flushing a gzip stream after every record would hurt the compression ratio, the compressed
+    /// output is only written to the file in larger chunks. Use
+    /// [`LoggerBuilder::with_flush_interval()`] to periodically write everything logged so far to
+    /// the file, at a small cost in compression ratio. Error records are always written to the file
+    /// right away so they aren't lost in a crash. The gzip stream is finished when
+    /// [`shutdown()`][crate::shutdown()] is called. If the process crashes before that, then
+    /// everything up to the last flush can still be decompressed, but tools like `gunzip` will
+    /// report that the file is truncated.
+    #[cfg(feature = "flate2")]
+    GzipFile(PathBuf),
+    /// Keep the last `size` bytes of log output in a fixed size buffer in memory. When the buffer
+    /// is full, the oldest output is overwritten. The buffer is allocated up front, so it's
+    /// included in core dumps, and it can be read using
+    /// [`ring_buffer_contents()`][crate::ring_buffer_contents()], for instance from a crash
+    /// handler. This is useful for intermittent crashes where the log would otherwise be lost.
+    RingBuffer { size: usize },
+    /// Write to the Web console using `console.log()`, or `console.error()` for errors and
+    /// warnings. This is only available when compiling to WebAssembly with the `wasm-console`
+    /// feature enabled, and it requires the host to provide the imports described in the readme.
+    #[cfg(all(target_arch = "wasm32", feature = "wasm-console"))]
+    Console,
     // TODO: Functions
 }
 
@@ -76,19 +339,157 @@ impl Display for SetTargetError {
     }
 }
 
-/// An error raised when setting a logger after one has already been set.
-// This is the same as `log::SetLoggerError`, except that we can create one ourselves.
+/// An error raised when setting a custom timestamp format. This can be converted back to the
+/// builder using `Into<Builder>`.
 #[derive(Debug)]
-pub struct SetLoggerError(());
+pub enum SetTimeFormatError {
+    InvalidFormat {
+        builder: LoggerBuilder,
+        format: String,
+        error: InvalidFormatDescription,
+    },
+}
+
+impl From<SetTimeFormatError> for LoggerBuilder {
+    fn from(value: SetTimeFormatError) -> Self {
+        match value {
+            SetTimeFormatError::InvalidFormat { builder, .. } => builder,
+        }
+    }
+}
+
+impl Error for SetTimeFormatError {}
+
+impl Display for SetTimeFormatError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SetTimeFormatError::InvalidFormat {
+                builder: _,
+                format,
+                error,
+            } => {
+                write!(f, "Invalid time format '{format}' ({error})")
+            }
+        }
+    }
+}
+
+/// How the current thread is shown for debug and trace messages. See
+/// [`LoggerBuilder::with_thread_format()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ThreadFormat {
+    /// Only show the thread's ID, e.g. `(2)`.
+    IdOnly,
+    /// Only show the thread's name, e.g. `(worker)`. Falls back to the thread's ID for unnamed
+    /// threads.
+    NameOnly,
+    /// Show both the thread's ID and its name, e.g. `(2, worker)`. The name is omitted for unnamed
+    /// threads. This is the default.
+    #[default]
+    IdAndName,
+    /// Don't show any thread information.
+    None,
+}
+
+/// Whether the logger's output should be colored. See [`LoggerBuilder::with_color_mode()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorMode {
+    /// Use the output target's default behavior. STDERR streams use colors when writing to a
+    /// terminal, taking the `NO_COLOR`, `CLICOLOR`, and `CLICOLOR_FORCE` environment variables into
+    /// account. Files never use colors. This is the default.
+    #[default]
+    Auto,
+    /// Always use colors, even when writing to a file or to the Windows debugger. Colors are
+    /// written as ANSI escape sequences, which not every debugger can display. The Windows
+    /// debugger's colors are decided independently of STDERR's, so they're also used when STDERR
+    /// is redirected.
+    Always,
+    /// Never use colors.
+    Never,
+}
+
+/// What to do when the local time offset cannot be determined while installing the logger. See
+/// [`LoggerBuilder::on_offset_failure()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OffsetFailureAction {
+    /// Silently fall back to UTC timestamps.
+    SilentUtc,
+    /// Print a warning to STDERR and fall back to UTC timestamps. This is the default.
+    #[default]
+    WarnUtc,
+    /// Fail to install the logger by returning an error from
+    /// [`build_global()`][LoggerBuilder::build_global()].
+    Error,
+}
+
+/// An error raised when installing the global logger, usually because one has already been set.
+// This is similar to `log::SetLoggerError`, except that we can create one ourselves.
+#[derive(Debug)]
+pub struct SetLoggerError(SetLoggerErrorKind);
+
+#[derive(Debug)]
+enum SetLoggerErrorKind {
+    /// A global logger was already configured.
+    AlreadySet,
+    /// Another logger was registered with the `log` crate. The NIH-log logger can still be used
+    /// through [`log_record()`][crate::log_record()].
+    LogFacadeTaken,
+    /// Like `LogFacadeTaken`, but the other logger is a different version of NIH-log linked into
+    /// the same binary.
+    OtherVersionInstalled,
+    /// The local time offset could not be determined, and the builder was configured to error out
+    /// in that case.
+    LocalTimeOffset,
+    /// Two mutually exclusive builder options were used at the same time.
+    ConflictingOptions(&'static str, &'static str),
+    /// The same signal was passed to [`LoggerBuilder::with_signal_control()`] for increasing and
+    /// decreasing the log level.
+    SameControlSignals(i32),
+    /// Colors were forced with [`ColorMode::Always`] for [`OutputTarget::RawStderr`], which never
+    /// writes colors.
+    RawStderrColors,
+}
 
 impl Error for SetLoggerError {}
 
 impl Display for SetLoggerError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "Tried to set a global logger after one has already been configured"
-        )
+        match self.0 {
+            SetLoggerErrorKind::AlreadySet => write!(
+                f,
+                "Tried to set a global logger after one has already been configured"
+            ),
+            SetLoggerErrorKind::LogFacadeTaken => write!(
+                f,
+                "Another logger has already been registered with the log crate, log messages can \
+                 only be sent to this logger through nih_log::log_record()"
+            ),
+            SetLoggerErrorKind::OtherVersionInstalled => write!(
+                f,
+                "Another version of NIH-log has already been registered with the log crate. \
+                 Multiple versions of NIH-log are linked into this binary, and this version ({}) \
+                 can only be used through nih_log::log_record()",
+                env!("CARGO_PKG_VERSION")
+            ),
+            SetLoggerErrorKind::LocalTimeOffset => {
+                write!(f, "Could not determine the local time offset")
+            }
+            SetLoggerErrorKind::ConflictingOptions(first, second) => write!(
+                f,
+                "The LoggerBuilder::{first}() and LoggerBuilder::{second}() options cannot be \
+                 combined"
+            ),
+            SetLoggerErrorKind::SameControlSignals(signal) => write!(
+                f,
+                "LoggerBuilder::with_signal_control() needs two different signals, but both were \
+                 set to {signal}"
+            ),
+            SetLoggerErrorKind::RawStderrColors => write!(
+                f,
+                "OutputTarget::RawStderr never uses colors, so it cannot be combined with \
+                 ColorMode::Always"
+            ),
+        }
     }
 }
 
@@ -99,56 +500,440 @@ impl LoggerBuilder {
         Self {
             max_log_level,
             always_show_module_path: false,
+            clickable_locations: false,
+            thread_format: ThreadFormat::default(),
+            os_thread_id: false,
+            show_main_thread_name: false,
+            hide_unnamed_threads: false,
+            message_only: false,
+            plain_info: false,
+            logfmt: false,
+            line_prefix: None,
+            column_separator: String::from(" "),
+            multiline_indent: false,
+            wrap_width: None,
+            normalize_newlines: false,
+            line_capacity: 0,
+            sequence_numbers: false,
+            interline_delta: false,
+            time_format: None,
+            monotonic_timestamps: false,
+            timestamps: true,
+            backtrace_level: LevelFilter::Off,
+            error_context_lines: None,
+            capture_early: false,
             output_target: None,
+            default_target: None,
+            level_routes: Vec::new(),
+            color_mode: ColorMode::default(),
+            theme: Theme::default(),
+            level_labels: [
+                Level::Error,
+                Level::Warn,
+                Level::Info,
+                Level::Debug,
+                Level::Trace,
+            ]
+            .map(|level| level_tag(level).to_owned()),
+            align_level_tags: false,
+            unbuffered: false,
+            buffer_capacity: None,
+            flush_interval: None,
+            flush_threshold: None,
             module_blacklist: HashSet::new(),
+            hidden_module_paths: HashSet::new(),
+            highlighted_crates: HashSet::new(),
+            show_target: false,
+            filter_fn: None,
+            record_hook: None,
+            chained_logger: None,
+            session_header: false,
+            header_fields: Vec::new(),
+            session_footer: false,
+            utf8_bom: false,
+            offset_failure_action: OffsetFailureAction::default(),
+            quiet: false,
+            level_change_markers: true,
+            signal_control: None,
+            reentrancy_guard: true,
+            create_log_dirs: false,
+            close_files_before_fork: false,
+            reentrancy_report: None,
+        }
+    }
+
+    /// Create a builder using the maximum log level selected through the `level-*` cargo features.
+    /// See [`FEATURE_MAX_LEVEL`]. If none of those features are enabled, then this uses
+    /// [`LevelFilter::Debug`] for debug builds and [`LevelFilter::Info`] for release builds. When
+    /// the level is `Debug` or `Trace`, the module path is always shown as if
+    /// [`always_show_module_path()`][Self::always_show_module_path()] was called.
+    pub fn from_features() -> Self {
+        let max_log_level = match FEATURE_MAX_LEVEL {
+            Some(level) => level,
+            None if cfg!(debug_assertions) => LevelFilter::Debug,
+            None => LevelFilter::Info,
+        };
+
+        let builder = Self::new(max_log_level);
+        if max_log_level >= LevelFilter::Debug {
+            builder.always_show_module_path()
+        } else {
+            builder
+        }
+    }
+
+    /// Create a builder that's configured similarly to `env_logger`'s default configuration, to
+    /// ease migrating from `env_logger`. The log level is parsed from the `RUST_LOG` environment
+    /// variable using `env_logger`'s directive syntax (e.g. `warn,my_crate=debug`), and the module
+    /// path is always shown. Per-module levels are implemented using a filter function, so
+    /// calling [`with_filter_fn()`][Self::with_filter_fn()] on the resulting builder will disable
+    /// those. Regular expression filters (`/regex`) are not supported and are ignored.
+    ///
+    /// Like with `env_logger`, only errors are logged if `RUST_LOG` is not set, and modules not
+    /// matching any directive are not logged unless a default level is set. A directive for `foo`
+    /// applies to `foo` and its submodules like `foo::bar`, but not to `foobar`.
+    pub fn from_env_logger_style() -> Self {
+        let spec = std::env::var("RUST_LOG").unwrap_or_default();
+        let (default_level, module_levels) = parse_rust_log(&spec);
+        let max_log_level = module_levels
+            .iter()
+            .map(|(_, level)| *level)
+            .fold(default_level, std::cmp::max);
+
+        let builder = Self::new(max_log_level).always_show_module_path();
+        if module_levels.is_empty() {
+            return builder;
         }
+
+        builder.with_filter_fn(Box::new(move |metadata| {
+            metadata.level() <= rust_log_level(metadata.target(), default_level, &module_levels)
+        }))
     }
 
     /// Install the configured logger as the global logger. The global logger can only be set once.
+    /// Returns an error if mutually exclusive options were combined, like
+    /// [`message_only()`][Self::message_only()] and [`with_logfmt()`][Self::with_logfmt()].
+    ///
+    /// If another logger has already been registered with the `log` crate, then this function
+    /// returns an error but the logger is still installed as NIH-log's global logger. In that case
+    /// records can still be sent to it directly using [`log_record()`][crate::log_record()].
     pub fn build_global(self) -> Result<(), SetLoggerError> {
+        let replay_early_records = self.capture_early;
+        let (logger, flush_interval) = self.build()?;
+
+        // We store a global logger instance and then set a static reference to that as the global
+        // logger. This way we can access the global logger instance later if it needs to be
+        // reconfigured at runtime, or if another logger was already registered with the `log`
+        // crate.
+        match LOGGER_INSTANCE.try_insert(logger) {
+            Ok(logger_instance) => {
+                start_global_logger(logger_instance, flush_interval, replay_early_records);
+                // If `init_early()` was called, then the proxy that forwards to the global logger
+                // has already been registered with the `log` crate
+                if !early::is_installed() {
+                    log::set_logger(logger_instance).map_err(|_| {
+                        SetLoggerError(if log_facade_is_nih_log() {
+                            SetLoggerErrorKind::OtherVersionInstalled
+                        } else {
+                            SetLoggerErrorKind::LogFacadeTaken
+                        })
+                    })?;
+                }
+                mark_as_log_facade(logger_instance);
+
+                Ok(())
+            }
+            Err(_) => Err(SetLoggerError(SetLoggerErrorKind::AlreadySet)),
+        }
+    }
+
+    /// Install the configured logger as the global logger, but only if no logger has been
+    /// registered with the `log` crate yet and NIH-log's global logger has not yet been set.
+    /// Returns whether the logger was installed. Unlike [`build_global()`][Self::build_global()],
+    /// this never returns an error and it never installs NIH-log's global logger when another
+    /// logger is already in use. This is useful for libraries that want logging to work out of the
+    /// box while still deferring to the application's choice of logger. If mutually exclusive
+    /// options were combined, then nothing is installed and this returns `false`.
+    pub fn build_global_if_none(self) -> bool {
+        if LOGGER_INSTANCE.get().is_some() {
+            return false;
+        }
+        let replay_early_records = self.capture_early;
+        let Ok((logger, flush_interval)) = self.build() else {
+            return false;
+        };
+
+        // The `log` crate doesn't expose whether a logger has already been registered, so the only
+        // way to find out is to try registering one. Since NIH-log's global logger can't be removed
+        // again once it's set, a proxy that forwards to the global logger is registered first.
+        // `init_early()` already registered that proxy.
+        if !early::is_installed() && log::set_logger(&GlobalLoggerProxy).is_err() {
+            return false;
+        }
+
+        match LOGGER_INSTANCE.try_insert(logger) {
+            Ok(logger_instance) => {
+                start_global_logger(logger_instance, flush_interval, replay_early_records);
+                mark_as_log_facade(logger_instance);
+
+                true
+            }
+            // Another thread installed NIH-log's global logger in the meantime. The proxy forwards
+            // to that logger instead.
+            Err(_) => false,
+        }
+    }
+
+    /// Build the logger from the builder's configuration without installing it. Also returns the
+    /// interval for the flush thread, which can only be started once the logger has a static
+    /// lifetime.
+    pub(crate) fn build(self) -> Result<(Logger, Option<Duration>), SetLoggerError> {
+        self.validate()?;
+
         // The time crate prevents us from getting the local time offset on Linux because other
         // threads may modify the environment. When this logger is being initialized that should not
-        // be the case.
+        // be the case. Newer versions of the time crate no longer need this, but this crate still
+        // supports older versions.
+        #[allow(deprecated)]
         unsafe {
             time::util::local_offset::set_soundness(time::util::local_offset::Soundness::Unsound)
         };
-        let local_time_offset = time::UtcOffset::current_local_offset().unwrap_or_else(|_| {
-            eprintln!("Could not get the local time offset, defaulting to UTC");
-            time::UtcOffset::UTC
-        });
+        let local_time_offset = time::UtcOffset::current_local_offset();
+        #[allow(deprecated)]
         unsafe {
             time::util::local_offset::set_soundness(time::util::local_offset::Soundness::Sound)
         };
+        let local_time_offset = match (local_time_offset, self.offset_failure_action) {
+            (Ok(offset), _) => offset,
+            (Err(_), OffsetFailureAction::SilentUtc) => time::UtcOffset::UTC,
+            (Err(_), OffsetFailureAction::WarnUtc) => {
+                eprintln!("Could not get the local time offset, defaulting to UTC");
+                time::UtcOffset::UTC
+            }
+            (Err(_), OffsetFailureAction::Error) => {
+                return Err(SetLoggerError(SetLoggerErrorKind::LocalTimeOffset))
+            }
+        };
 
-        let max_log_level = self.max_log_level;
+        // `NIH_LOG` may point to a configuration file that also overrides the log level. If the
+        // `log` crate's `max_level_*` or `release_max_level_*` features are used, then log messages
+        // above that level are compiled out. Capping the level here keeps the level reported by the
+        // logger consistent with that.
+        let mut env_config = EnvConfig::cached().clone();
+        let max_log_level = env_config
+            .level
+            .unwrap_or(self.max_log_level)
+            .min(log::STATIC_MAX_LEVEL);
+        let route_max_level = self
+            .level_routes
+            .iter()
+            .map(|(levels, _)| levels.max_level())
+            .max()
+            .unwrap_or(LevelFilter::Off)
+            .min(log::STATIC_MAX_LEVEL);
         let always_show_module_path = self.always_show_module_path;
+        let flush_interval = self.flush_interval;
+        let unbuffered = self.unbuffered;
+        let buffer_capacity = self.buffer_capacity;
+        let configure_target = |target: OutputTargetImpl| {
+            let mut target = target.with_color_mode(self.color_mode);
+            if let Some(capacity) = buffer_capacity {
+                target = target.with_buffer_capacity(capacity);
+            }
+            if unbuffered {
+                target.unbuffered()
+            } else {
+                target
+            }
+        };
+
+        // Picking an output target happens in four steps:
+        // - If `LoggerBuilder::with_output_target()` was called, that target is used.
+        // - If the `NIH_LOG` environment variable is non-empty, then that is parsed.
+        // - If `LoggerBuilder::with_default_target()` was called, then that target is used.
+        // - Otherwise a dynamic target is used that writes to either STDERR or a WinDbg
+        //   debugger depending on whether a Windows debugger is present.
+        let output_target = match (self.output_target, self.default_target) {
+            (Some(target), _) => target,
+            (None, Some(default_target)) if env_config.target == EnvTarget::Default => {
+                match open_output_target(default_target, self.create_log_dirs) {
+                    Ok(target) => target,
+                    Err((path, err)) => {
+                        env_config.warn(format!(
+                            "Could not open '{}' for logging, falling back to STDERR: {err}",
+                            path.display()
+                        ));
+                        OutputTargetImpl::from_env_config(&mut env_config, self.create_log_dirs)
+                    }
+                }
+            }
+            (None, _) => OutputTargetImpl::from_env_config(&mut env_config, self.create_log_dirs),
+        };
+
+        #[cfg(unix)]
+        let signal_control = match self.signal_control {
+            Some((increase_signal, decrease_signal)) => {
+                match crate::signal::install_handlers(increase_signal, decrease_signal) {
+                    Ok(()) => true,
+                    Err(err) => {
+                        env_config.warn(format!(
+                            "Could not install the signal handlers for changing the log level: {err}"
+                        ));
+                        false
+                    }
+                }
+            }
+            None => false,
+        };
+        #[cfg(not(unix))]
+        let signal_control = false;
+
         let logger = Logger {
             max_log_level,
+            route_max_level,
             always_show_module_path,
-            // Picking an output target happens in three steps:
-            // - If `LoggerBuilder::with_output_target()` was called, that target is used.
-            // - If the `NIH_LOG` environment variable is non-empty, then that is parsed.
-            // - Otherwise a dynamic target is used that writes to either STDERR or a WinDbg
-            //   debugger depending on whether a Windows debugger is present.
-            output_target: Mutex::new(
-                self.output_target
-                    .unwrap_or_else(OutputTargetImpl::default_from_environment),
-            ),
+            clickable_locations: self.clickable_locations,
+            thread_format: self.thread_format,
+            os_thread_id: self.os_thread_id,
+            show_main_thread_name: self.show_main_thread_name,
+            hide_unnamed_threads: self.hide_unnamed_threads,
+            message_only: self.message_only,
+            plain_info: self.plain_info,
+            logfmt: self.logfmt,
+            line_prefix: self.line_prefix,
+            column_separator: self.column_separator,
+            multiline_indent: self.multiline_indent,
+            wrap_width: self.wrap_width,
+            normalize_newlines: self.normalize_newlines,
+            line_capacity: self.line_capacity,
+            sequence_numbers: self.sequence_numbers,
+            next_sequence_number: AtomicU64::new(0),
+            interline_delta: self.interline_delta,
+            time_format: self.time_format,
+            monotonic_timestamps: self.monotonic_timestamps,
+            timestamps: self.timestamps,
+            backtrace_level: self.backtrace_level,
+            error_context_ring: self
+                .error_context_lines
+                .filter(|lines| *lines > 0)
+                .map(ErrorContextRing::new),
+            start_time: Instant::now(),
+            last_record_nanos: AtomicU64::new(u64::MAX),
+            output_target: Mutex::new(configure_target(output_target)),
+            level_routes: self
+                .level_routes
+                .into_iter()
+                .map(|(level, target)| (level, Mutex::new(configure_target(target))))
+                .collect(),
+            color_mode: self.color_mode,
+            unbuffered: self.unbuffered,
+            buffer_capacity: self.buffer_capacity,
+            flush_threshold: self.flush_threshold,
+            flush_thread: FlushThread::default(),
+            theme: self.theme,
+            ansi_level_tags: ansi_level_tags(&self.theme, &self.level_labels),
+            level_tag_width: if self.align_level_tags {
+                self.level_labels
+                    .iter()
+                    .map(|label| label.chars().count())
+                    .max()
+                    .unwrap_or(0)
+            } else {
+                0
+            },
+            level_labels: self.level_labels,
             local_time_offset,
 
             module_blacklist: self.module_blacklist,
+            hidden_module_paths: self.hidden_module_paths,
+            highlighted_crates: self.highlighted_crates,
+            show_target: self.show_target,
+            filter_fn: self.filter_fn,
+            record_hook: self.record_hook,
+            session_header: self.session_header.then_some(self.header_fields),
+            session_footer: self.session_footer,
+            session_footer_written: AtomicBool::new(false),
+            utf8_bom: self.utf8_bom,
+            bytes_written: AtomicU64::new(0),
+            paused: AtomicBool::new(false),
+            quiet: AtomicBool::new(self.quiet),
+            level_change_markers: self.level_change_markers,
+            signal_control,
+            level_adjustment: AtomicIsize::new(0),
+            is_log_facade: AtomicBool::new(false),
+            chained_logger: self.chained_logger,
+            reentrancy_guard: self.reentrancy_guard,
+            create_log_dirs: self.create_log_dirs,
+            close_files_before_fork: self.close_files_before_fork,
+            env_config,
+            reentrancy_report: self.reentrancy_report,
+            reentrancy_reported: AtomicBool::new(false),
         };
 
-        // We store a global logger instance and then set a static reference to that as the global
-        // logger. This way we can access the global logger instance later if it needs to be
-        // reconfigured at runtime
-        match LOGGER_INSTANCE.try_insert(logger) {
-            Ok(logger_instance) => {
-                log::set_logger(logger_instance).map_err(|_| SetLoggerError(()))?;
-                log::set_max_level(max_log_level);
-                Ok(())
+        Ok((logger, flush_interval))
+    }
+
+    /// Check whether any mutually exclusive options have been combined, whether the same signal was
+    /// used for increasing and decreasing the log level, or whether colors were forced for a target
+    /// that can't write them. Rather than silently ignoring one of the options, this returns an
+    /// error describing the conflict.
+    fn validate(&self) -> Result<(), SetLoggerError> {
+        let conflicts = [
+            (
+                self.message_only && self.logfmt,
+                "message_only",
+                "with_logfmt",
+            ),
+            (self.plain_info && self.logfmt, "plain_info", "with_logfmt"),
+            (
+                self.message_only && self.multiline_indent,
+                "message_only",
+                "with_multiline_indent",
+            ),
+            (
+                self.logfmt && self.multiline_indent,
+                "with_logfmt",
+                "with_multiline_indent",
+            ),
+            (
+                self.logfmt && self.wrap_width.is_some(),
+                "with_logfmt",
+                "wrap_at",
+            ),
+            (
+                self.message_only && self.wrap_width.is_some(),
+                "message_only",
+                "wrap_at",
+            ),
+            (
+                self.monotonic_timestamps && self.time_format.is_some(),
+                "with_monotonic_timestamps",
+                "with_time_format",
+            ),
+        ];
+
+        if self.color_mode == ColorMode::Always
+            && self
+                .output_target
+                .as_ref()
+                .is_some_and(OutputTargetImpl::is_raw_stderr)
+        {
+            return Err(SetLoggerError(SetLoggerErrorKind::RawStderrColors));
+        }
+
+        if let Some((increase_signal, decrease_signal)) = self.signal_control {
+            if increase_signal == decrease_signal {
+                return Err(SetLoggerError(SetLoggerErrorKind::SameControlSignals(
+                    increase_signal,
+                )));
             }
-            Err(_) => Err(SetLoggerError(())),
+        }
+
+        match conflicts.into_iter().find(|(conflicts, _, _)| *conflicts) {
+            Some((_, first, second)) => Err(SetLoggerError(
+                SetLoggerErrorKind::ConflictingOptions(first, second),
+            )),
+            None => Ok(()),
         }
     }
 
@@ -159,6 +944,363 @@ impl LoggerBuilder {
         self
     }
 
+    /// Show a record's target in parentheses after the module path when the record was logged with
+    /// an explicit target, e.g. `log::debug!(target: "network", ...)`. Like the module path, this
+    /// is only shown for debug and trace messages unless
+    /// [`always_show_module_path()`][Self::always_show_module_path()] is used.
+    pub fn show_target(mut self) -> Self {
+        self.show_target = true;
+        self
+    }
+
+    /// Write the file names and line numbers shown for trace messages as clickable OSC 8
+    /// hyperlinks. This is only done when writing to a terminal that supports colors.
+    pub fn with_clickable_locations(mut self) -> Self {
+        self.clickable_locations = true;
+        self
+    }
+
+    /// Configure how the current thread is shown for debug and trace messages. Defaults to
+    /// [`ThreadFormat::IdAndName`].
+    pub fn with_thread_format(mut self, format: ThreadFormat) -> Self {
+        self.thread_format = format;
+        self
+    }
+
+    /// Don't show the current thread for debug and trace messages at all. This also skips looking
+    /// up the current thread for every message, which is useful for single-threaded applications.
+    /// This is a shorthand for `with_thread_format(ThreadFormat::None)`.
+    pub fn without_thread_info(self) -> Self {
+        self.with_thread_format(ThreadFormat::None)
+    }
+
+    /// Show the operating system's thread ID instead of Rust's [`ThreadId`][std::thread::ThreadId].
+    /// Rust's thread IDs are a per-process counter, while the operating system's IDs match what
+    /// tools like `top`, `htop`, and `perf` show. This uses `gettid()` on Linux,
+    /// `pthread_threadid_np()` on macOS, and `GetCurrentThreadId()` on Windows. Rust's thread ID
+    /// is still used on other platforms. This also applies to the logfmt format's `thread` field.
+    pub fn with_os_thread_id(mut self) -> Self {
+        self.os_thread_id = true;
+        self
+    }
+
+    /// Show the main thread's name for debug and trace messages. By default the name is omitted
+    /// for the main thread since it doesn't carry any special meaning.
+    pub fn show_main_thread_name(mut self) -> Self {
+        self.show_main_thread_name = true;
+        self
+    }
+
+    /// Don't show any thread information for threads without a name. Unless
+    /// [`show_main_thread_name()`][Self::show_main_thread_name()] is also used, this includes the
+    /// main thread.
+    pub fn hide_unnamed_threads(mut self) -> Self {
+        self.hide_unnamed_threads = true;
+        self
+    }
+
+    /// Only write the log message itself, without a timestamp, log level, thread, module path, or
+    /// source location. Useful when the log output is displayed by another application that
+    /// already adds this information.
+    pub fn message_only(mut self) -> Self {
+        self.message_only = true;
+        self
+    }
+
+    /// Write info records without a timestamp, level tag, or any other decorations, just like in
+    /// [`message_only()`][Self::message_only()] mode. Records on the other levels keep their usual
+    /// formatting. This is useful for command line applications that use the info level for their
+    /// regular output and the other levels for diagnostics. The line prefix and sequence number
+    /// are still written if those are enabled. This cannot be combined with
+    /// [`with_logfmt()`][Self::with_logfmt()].
+    pub fn plain_info(mut self) -> Self {
+        self.plain_info = true;
+        self
+    }
+
+    /// Write records in the logfmt format, as a single line of `key=value` pairs. The fields are
+    /// `ts`, `level`, `target`, `thread`, `thread_name`, `file`, `line`, and `msg`, with
+    /// `thread_name`, `file`, and `line` being omitted when they're not known. Values containing
+    /// spaces or special characters are quoted. Colors are never used in this format.
+    pub fn with_logfmt(mut self) -> Self {
+        self.logfmt = true;
+        self
+    }
+
+    /// Write a fixed string at the start of every line, before the timestamp. Useful for telling
+    /// apart the output from multiple instances of the same plugin. This is also written in
+    /// [`message_only()`][Self::message_only()] mode.
+    pub fn with_line_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.line_prefix = Some(prefix.into());
+        self
+    }
+
+    /// Change the separator written between the columns of a log line. The columns are the sequence
+    /// number, the timestamp, the level tag, the thread and module path, the source location, and
+    /// the message. Defaults to a single space. For instance, `" | "` results in lines like
+    /// `12:34:56 | [DEBUG] | (1) my_crate: | message`. This is not used for the logfmt format or
+    /// in [`message_only()`][Self::message_only()] mode.
+    pub fn with_column_separator(mut self, separator: impl Into<String>) -> Self {
+        self.column_separator = separator.into();
+        self
+    }
+
+    /// Show the time passed since the previous record after the timestamp, like `+0.123s`. The
+    /// first record shows `+0.000s`. This makes it easy to spot stalls. With the logfmt format this
+    /// is written as a `delta` field instead, and it's not shown when only writing the message.
+    pub fn with_interline_delta(mut self) -> Self {
+        self.interline_delta = true;
+        self
+    }
+
+    /// Use a custom format for the timestamp at the start of every line instead of the default
+    /// `[hour]:[minute]:[second]`. This accepts the full version 2 format description syntax from
+    /// the `time` crate, including components like `[week_number]`, `[ordinal]`, and
+    /// `[weekday repr:short]`. See
+    /// <https://time-rs.github.io/book/api/format-description.html> for the syntax. Returns an
+    /// error if the format description could not be parsed. The logfmt format always uses RFC
+    /// 3339 timestamps.
+    #[allow(clippy::result_large_err)]
+    pub fn with_time_format(mut self, format: &str) -> Result<Self, SetTimeFormatError> {
+        match time::format_description::parse_owned::<2>(format) {
+            Ok(time_format) => {
+                self.time_format = Some(time_format);
+                Ok(self)
+            }
+            Err(error) => Err(SetTimeFormatError::InvalidFormat {
+                builder: self,
+                format: format.to_owned(),
+                error,
+            }),
+        }
+    }
+
+    /// The same as [`with_time_format()`][Self::with_time_format()], but for a format description
+    /// that has already been parsed at compile time. Use the
+    /// [`time_format!()`][crate::time_format!()] macro to create one, so mistakes in the format
+    /// description result in compile errors instead of errors when building the logger.
+    pub fn with_time_format_static(mut self, format: &'static [FormatItem<'static>]) -> Self {
+        self.time_format = Some(OwnedFormatItem::from(format));
+        self
+    }
+
+    /// Replace the wall clock timestamp at the start of every line with the value of
+    /// `CLOCK_MONOTONIC` in seconds, like `12345.678901234`. On Linux this matches the timestamps
+    /// in the kernel log, which makes it possible to correlate the log with `dmesg` output and
+    /// kernel traces. Other platforms don't have an equivalent clock, so there this falls back to
+    /// the time since the logger was created. This cannot be combined with
+    /// [`with_time_format()`][Self::with_time_format()]. The logfmt format always uses RFC 3339
+    /// timestamps.
+    pub fn with_monotonic_timestamps(mut self) -> Self {
+        self.monotonic_timestamps = true;
+        self
+    }
+
+    /// Don't write a timestamp at the start of every line. This also hides the time since the
+    /// previous record from [`with_interline_delta()`][Self::with_interline_delta()], and it
+    /// omits the `ts` and `delta` fields from the logfmt format.
+    pub fn without_timestamps(mut self) -> Self {
+        self.timestamps = false;
+        self
+    }
+
+    /// Produce output that's the same on every machine and every run, for instance for snapshot
+    /// tests. Lines are written as `[LEVEL] module::path: message` without timestamps, thread
+    /// information, or colors. The module path is shown for every level, even for records logged
+    /// with an explicit target. Use [`show_target()`][Self::show_target()] to also show those
+    /// targets. This is a shorthand
+    /// for [`without_timestamps()`][Self::without_timestamps()],
+    /// [`without_thread_info()`][Self::without_thread_info()],
+    /// [`with_color_mode(ColorMode::Never)`][Self::with_color_mode()], and
+    /// [`always_show_module_path()`][Self::always_show_module_path()]. Trace messages still
+    /// include their file name and line number.
+    pub fn deterministic(self) -> Self {
+        self.without_timestamps()
+            .without_thread_info()
+            .with_color_mode(ColorMode::Never)
+            .always_show_module_path()
+    }
+
+    /// Write a backtrace after the message for records at or above `level`, for instance
+    /// `LevelFilter::Error` to include backtraces with all errors. Capturing a backtrace is
+    /// expensive, so this should only be used for rare levels. Backtraces are only captured when
+    /// the `RUST_BACKTRACE` or `RUST_LIB_BACKTRACE` environment variables enable them. With the
+    /// logfmt format the backtrace is written as a `backtrace` field, and it's not written when
+    /// only writing the message.
+    pub fn backtrace_on_error(mut self, level: LevelFilter) -> Self {
+        self.backtrace_level = level;
+        self
+    }
+
+    /// Keep the last `lines` records that were filtered out because of their log level in memory,
+    /// and write them right before the next error record. This includes trace and debug records
+    /// when the logger only logs warnings and errors, so the log contains the full context leading
+    /// up to an error without being flooded with trace output during normal operation. The
+    /// buffered records are written without colors, and records that were already logged are not
+    /// repeated. Records excluded by the module blacklist or a filter function are not kept.
+    ///
+    /// Since the `log` crate's global maximum level can no longer be used to skip these records,
+    /// every record up to the `log` crate's static maximum level is formatted when this is
+    /// enabled. Consider capping the level with the `log` crate's `max_level_*` features when
+    /// logging a lot at the trace level.
+    pub fn with_error_context_ring(mut self, lines: usize) -> Self {
+        self.error_context_lines = Some(lines);
+        self
+    }
+
+    /// Write the records that were logged between [`init_early()`][crate::init_early()] and
+    /// installing this logger, with their original timestamps. Without this those records are
+    /// discarded. The records are written right after the session header and before anything
+    /// logged after installation, and they're filtered using this logger's configuration. At most
+    /// 1024 records are kept, and a warning is logged if any additional records were dropped.
+    pub fn capture_early(mut self) -> Self {
+        self.capture_early = true;
+        self
+    }
+
+    /// Change the colors used when the output target supports colors. See [`Theme`] for the
+    /// available presets. This does not affect whether colors are used, use
+    /// [`with_color_mode()`][Self::with_color_mode()] for that.
+    pub fn with_theme(mut self, theme: Theme) -> Self {
+        self.theme = theme;
+        self
+    }
+
+    /// Replace the tag written for a log level, for instance `[E]` instead of `[ERROR]`. The tag is
+    /// written as is, so it should include any brackets. This does not affect the level names used
+    /// in the logfmt format.
+    pub fn with_level_label(mut self, level: Level, label: impl Into<String>) -> Self {
+        self.level_labels[level as usize - 1] = label.into();
+        self
+    }
+
+    /// Pad the level tags with trailing spaces so they all have the same width. This lines up the
+    /// rest of the log lines. The width is that of the longest tag, including any labels set
+    /// through [`with_level_label()`][Self::with_level_label()].
+    pub fn align_level_tags(mut self) -> Self {
+        self.align_level_tags = true;
+        self
+    }
+
+    /// Color the timestamp at the start of every line when the output target supports colors. This
+    /// overrides the current theme's timestamp color. See [`with_theme()`][Self::with_theme()].
+    pub fn with_timestamp_color(mut self, color: Color) -> Self {
+        self.theme.timestamp = Some(color);
+        self
+    }
+
+    /// Color the module path and target when the output target supports colors. This overrides
+    /// the current theme's module path color. See [`with_theme()`][Self::with_theme()].
+    pub fn with_module_color(mut self, color: Color) -> Self {
+        self.theme.module_path = Some(color);
+        self
+    }
+
+    /// Prefix every record with a monotonically increasing sequence number, like `#42`. With the
+    /// logfmt format this is written as a `seq=42` field instead. This makes it possible to detect
+    /// dropped or reordered lines. Records written to multiple targets share the same number, and
+    /// records that are filtered out don't consume a number.
+    pub fn with_sequence_numbers(mut self) -> Self {
+        self.sequence_numbers = true;
+        self
+    }
+
+    /// Don't buffer the output. Normally every record is formatted into a buffer which is then
+    /// flushed at the end of the line. With this option every write goes directly to STDERR or to
+    /// the log file instead. This can be useful when the output is interleaved with a child
+    /// process's output. The Windows debugger is still line buffered since it needs to be sent
+    /// whole lines.
+    pub fn unbuffered(mut self) -> Self {
+        self.unbuffered = true;
+        self
+    }
+
+    /// Change the size of the write buffer used for files and for [`OutputTarget::RawStderr`]. Each
+    /// record is still flushed at the end of the line, so this only matters for records that don't
+    /// fit in the buffer. The default is 8 KiB. A capacity of zero means that writes are not
+    /// buffered at all, which is the same as [`unbuffered()`][Self::unbuffered()] for these
+    /// targets.
+    pub fn with_buffer_capacity(mut self, capacity: usize) -> Self {
+        self.buffer_capacity = Some(capacity);
+        self
+    }
+
+    /// Flush the logger from a background thread every `interval`. The output targets are normally
+    /// already flushed after every record, so this is mostly useful together with
+    /// [`with_flush_threshold()`][Self::with_flush_threshold()], for gzip compressed files, and for
+    /// loggers added through [`chain_to()`][Self::chain_to()] that buffer their output, so that
+    /// buffered output doesn't sit around during quiet periods. The thread is stopped by
+    /// [`shutdown()`][crate::shutdown()].
+    pub fn with_flush_interval(mut self, interval: Duration) -> Self {
+        self.flush_interval = Some(interval);
+        self
+    }
+
+    /// Only flush an output target after a record once more than `bytes` bytes have been written
+    /// to it since it was last flushed, instead of after every record. Error records are still
+    /// flushed immediately since they may be followed by a crash, and for
+    /// [`OutputTarget::GzipFile`] they also emit a gzip sync point. This reduces the number of
+    /// syscalls when logging a lot, at the cost of other records showing up later. The write
+    /// buffer flushes itself when it's full, so thresholds above the buffer's capacity have no
+    /// additional effect. See [`with_buffer_capacity()`][Self::with_buffer_capacity()]. Combine
+    /// this with [`with_flush_interval()`][Self::with_flush_interval()] so buffered records are
+    /// still written during quiet periods.
+    pub fn with_flush_threshold(mut self, bytes: u64) -> Self {
+        self.flush_threshold = Some(bytes);
+        self
+    }
+
+    /// Override whether the output target uses colors. By default STDERR output is colored
+    /// depending on the environment, and files are never colored. See [`ColorMode`] for more
+    /// information. [`ColorMode::Always`] cannot be combined with [`OutputTarget::RawStderr`].
+    pub fn with_color_mode(mut self, mode: ColorMode) -> Self {
+        self.color_mode = mode;
+        self
+    }
+
+    /// Indent the continuation lines of messages that span multiple lines so they line up with the
+    /// first line of the message, after the timestamp, log level, and other context. Requires the
+    /// message to be formatted into a thread-local scratch buffer first, see
+    /// [`with_line_capacity()`][Self::with_line_capacity()].
+    pub fn with_multiline_indent(mut self) -> Self {
+        self.multiline_indent = true;
+        self
+    }
+
+    /// Hard-wrap messages so no line is longer than `width` columns, for instance for fixed-width
+    /// debug consoles. Unlike truncating the message, all of the message is kept. Wrapped lines are
+    /// indented to line up with the first line of the message like with
+    /// [`with_multiline_indent()`][Self::with_multiline_indent()], which this implies. The width
+    /// includes the timestamp, log level, and other context before the message, but every line
+    /// contains at least one character of the message. Columns are counted in characters, so wide
+    /// characters and escape sequences in the message are not accounted for. Context key-value
+    /// pairs and backtraces are not wrapped. This cannot be combined with
+    /// [`message_only()`][Self::message_only()] or [`with_logfmt()`][Self::with_logfmt()].
+    pub fn wrap_at(mut self, width: usize) -> Self {
+        self.wrap_width = Some(width);
+        self
+    }
+
+    /// Remove a single trailing newline from messages, so messages that already end in a newline
+    /// don't result in an empty line after the record. Like
+    /// [`with_multiline_indent()`][Self::with_multiline_indent()], this requires the message to be
+    /// formatted into a scratch buffer first.
+    pub fn normalize_newlines(mut self) -> Self {
+        self.normalize_newlines = true;
+        self
+    }
+
+    /// Hint the expected maximum length of a log message in bytes. Options like
+    /// [`with_multiline_indent()`][Self::with_multiline_indent()] need to format messages into a
+    /// thread-local scratch buffer before writing them. With this option that buffer's capacity is
+    /// reserved up front, so typical messages won't cause the buffer to be reallocated. This is
+    /// useful in combination with `assert_no_alloc`. Messages longer than this hint may still cause
+    /// allocations.
+    pub fn with_line_capacity(mut self, capacity: usize) -> Self {
+        self.line_capacity = capacity;
+        self
+    }
+
     /// Filter out log messages produced by the given crate.
     pub fn filter_crate(mut self, crate_name: impl Into<String>) -> Self {
         self.module_blacklist.insert(crate_name.into());
@@ -174,26 +1316,723 @@ impl LoggerBuilder {
         self
     }
 
+    /// Filter log messages using a custom function. This is consulted after the crate and module
+    /// filters. Records for which the function returns `false` are not logged. Useful for more
+    /// complex filtering needs that can't be expressed using the other filters.
+    pub fn with_filter_fn(
+        mut self,
+        filter_fn: Box<dyn Fn(&log::Metadata) -> bool + Send + Sync>,
+    ) -> Self {
+        self.filter_fn = Some(filter_fn);
+        self
+    }
+
+    /// Call a function for every record that passes the filters, before the record is formatted
+    /// and written. This can be used to update metrics or to trigger alerts for specific messages.
+    /// The function is called from the thread that logged the record, so it should be cheap. It
+    /// may log, but the function is not called again for records logged from within the function.
+    #[allow(clippy::type_complexity)]
+    pub fn with_record_hook(
+        mut self,
+        record_hook: Box<dyn Fn(&log::Metadata, &std::fmt::Arguments) + Send + Sync>,
+    ) -> Self {
+        self.record_hook = Some(record_hook);
+        self
+    }
+
+    /// Write a UTF-8 byte order mark when logging to a file. Some text editors on Windows need this
+    /// to detect that the file is encoded as UTF-8. Since log files are appended to, the byte order
+    /// mark is only written when the file is newly created or empty.
+    pub fn with_utf8_bom(mut self) -> Self {
+        self.utf8_bom = true;
+        self
+    }
+
+    /// Forward every log record to another logger in addition to writing it to NIH-log's own
+    /// output target. The other logger does its own filtering, so NIH-log's module filters and
+    /// filter function don't apply to it. The `log` crate's global maximum log level is still
+    /// determined by NIH-log's own configuration, which is usually
+    /// [`max_level()`][crate::max_level()], so more verbose records never reach either logger.
+    pub fn chain_to(mut self, logger: Box<dyn log::Log>) -> Self {
+        self.chained_logger = Some(logger);
+        self
+    }
+
+    /// Write a session header containing the current date and time when the logger is installed
+    /// and the log is written to a file. This makes it easier to tell separate sessions apart when
+    /// appending to the same log file.
+    pub fn with_session_header(mut self) -> Self {
+        self.session_header = true;
+        self
+    }
+
+    /// Write a session footer containing the current date and time to file targets when
+    /// [`nih_log::shutdown()`][crate::shutdown()] is called. A log without a footer then indicates
+    /// that the application did not shut down cleanly.
+    pub fn with_session_footer(mut self) -> Self {
+        self.session_footer = true;
+        self
+    }
+
+    /// Include additional `key=value` pairs in the session header, for instance to record the
+    /// application's version. Implies [`with_session_header()`][Self::with_session_header()].
+    pub fn with_header_fields(mut self, fields: &[(&str, &str)]) -> Self {
+        self.session_header = true;
+        self.header_fields.extend(
+            fields
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string())),
+        );
+        self
+    }
+
+    /// Configure what happens when the local time offset cannot be determined while installing the
+    /// logger. By default a warning is printed to STDERR and the logger falls back to UTC
+    /// timestamps.
+    pub fn on_offset_failure(mut self, action: OffsetFailureAction) -> Self {
+        self.offset_failure_action = action;
+        self
+    }
+
+    /// Don't show the module path for log messages produced by the given crate or module. The
+    /// messages themselves are still shown. Crate and module names are matched the same way as
+    /// in [`filter_module()`][Self::filter_module()].
+    pub fn hide_module_path_for(mut self, module_name: impl Into<String>) -> Self {
+        self.hidden_module_paths.insert(module_name.into());
+        self
+    }
+
+    /// Make the messages from the given crate or module stand out from the other messages, for
+    /// instance to highlight the application's own messages among messages from its dependencies.
+    /// Highlighted messages are prefixed with `▸`, and they're written in bold when the output
+    /// target supports ANSI colors. Crate and module names are matched the same way as in
+    /// [`filter_module()`][Self::filter_module()]. This only applies to the regular format, and not
+    /// to the logfmt format or when only writing the message. Can be called multiple times to
+    /// highlight more crates.
+    pub fn highlight_crate(mut self, crate_name: impl Into<String>) -> Self {
+        self.highlighted_crates.insert(crate_name.into());
+        self
+    }
+
+    /// Only log warnings and errors, for instance when a CLI application is run with `--quiet`.
+    /// This caps the maximum log level at [`LevelFilter::Warn`]. It never raises the level, so a
+    /// logger created with `new(LevelFilter::Error)` still only logs errors. Quiet mode can also be
+    /// toggled at runtime using [`set_quiet()`][crate::set_quiet()].
+    pub fn quiet(mut self) -> Self {
+        self.quiet = true;
+        self
+    }
+
+    /// Let the log level be changed at runtime by sending signals to the process, for instance
+    /// `with_signal_control(libc::SIGUSR1, libc::SIGUSR2)`. Every `increase_signal` makes the log
+    /// one level more verbose, up to the `log` crate's static maximum level, and every
+    /// `decrease_signal` makes it one level less verbose, down to [`LevelFilter::Off`]. Both are
+    /// relative to the level the logger was built with, and quiet mode still caps the result. Only
+    /// the regular output target's level is adjusted, the levels of level routes stay the same.
+    ///
+    /// The signal handlers only update an atomic counter. The new level is applied, and the level
+    /// change marker is written, the next time something is logged. Since the `log` crate can't be
+    /// told about these changes from within the signal handler, its global maximum level is set to
+    /// the static maximum level and records are instead filtered by the logger. This makes
+    /// filtered out log statements slightly more expensive. Any handlers previously installed for
+    /// these signals are replaced. If the handlers cannot be installed, then a warning is printed
+    /// and the option is ignored. Installing the logger fails if both signals are the same.
+    #[cfg(unix)]
+    pub fn with_signal_control(mut self, increase_signal: i32, decrease_signal: i32) -> Self {
+        self.signal_control = Some((increase_signal, decrease_signal));
+        self
+    }
+
+    /// Don't write a line when the effective log level changes at runtime. By default toggling
+    /// [`set_quiet()`][crate::set_quiet()] writes an info record like `log level changed from Info
+    /// to Warn`, regardless of the current log level, so it's clear why the log's verbosity
+    /// changes.
+    pub fn without_level_change_markers(mut self) -> Self {
+        self.level_change_markers = false;
+        self
+    }
+
+    /// Don't detect reentrant logging calls. Normally the logger detects when a log call is made
+    /// while the same thread is already writing to the logger, which can happen when using
+    /// `assert_no_alloc`'s log feature. In that case the message is written to a new output target
+    /// instead of deadlocking. This detection has a small cost for every log call.
+    ///
+    /// Only use this if you're certain that logging can never be reentrant, like in CLI
+    /// applications that don't use `assert_no_alloc`. Otherwise reentrant logging calls will
+    /// deadlock.
+    pub fn without_reentrancy_guard(mut self) -> Self {
+        self.reentrancy_guard = false;
+        self
+    }
+
+    /// Write a marker to `target` the first time a reentrant logging call is detected. Reentrant
+    /// logging calls are normally handled silently, see
+    /// [`without_reentrancy_guard()`][Self::without_reentrancy_guard()]. When using
+    /// `assert_no_alloc`, this lets you know that logging allocated memory inside of a section
+    /// where allocations are not allowed. The target is only opened when this happens, and only the
+    /// first occurrence is reported.
+    pub fn report_reentrant_logging(mut self, target: OutputTarget) -> Self {
+        self.reentrancy_report = Some(target);
+        self
+    }
+
+    /// Create the parent directories of log files if they don't exist yet. This applies to files
+    /// from the `NIH_LOG` environment variable and to files set through the builder. Since
+    /// [`with_output_target()`][Self::with_output_target()] and
+    /// [`route_level()`][Self::route_level()] open their files immediately, this needs to be
+    /// called before those functions.
+    pub fn create_log_dirs(mut self) -> Self {
+        self.create_log_dirs = true;
+        self
+    }
+
+    /// Close log files in [`prepare_for_fork()`][crate::prepare_for_fork()], so a child process
+    /// doesn't inherit their file descriptors. The files are opened again in append mode the next
+    /// time something is logged, in both the parent and the child process. Gzip compressed files
+    /// are finished, and later records are written as a new gzip member. Atomic files are closed
+    /// without being published.
+    pub fn close_files_before_fork(mut self) -> Self {
+        self.close_files_before_fork = true;
+        self
+    }
+
     /// Explicitly set the output target for the logger. This is normally set using the `NIH_LOG`
     /// environment variable. Returns an error if the target could not be set.
     #[allow(clippy::result_large_err)]
     pub fn with_output_target(mut self, target: OutputTarget) -> Result<Self, SetTargetError> {
-        self.output_target = Some(match target {
-            OutputTarget::Stderr => OutputTargetImpl::new_stderr(),
-            #[cfg(windows)]
-            OutputTarget::WinDbg => OutputTargetImpl::new_windbg(),
-            OutputTarget::File(path) => match OutputTargetImpl::new_file_path(&path) {
-                Ok(target) => target,
-                Err(error) => {
+        match open_output_target(target, self.create_log_dirs) {
+            Ok(target) => {
+                self.output_target = Some(target);
+                Ok(self)
+            }
+            Err((path, error)) => Err(SetTargetError::FileOpenError {
+                builder: self,
+                path,
+                error,
+            }),
+        }
+    }
+
+    /// Write the log output to a file handle that the application already opened, for instance a
+    /// securely created temporary file or a file descriptor inherited from a parent process. The
+    /// handle is wrapped in a buffered writer just like [`OutputTarget::File`], but since it may
+    /// not have a path, it's never closed or reopened. [`rotate_file()`][crate::rotate_file()] and
+    /// [`close_files_before_fork()`][Self::close_files_before_fork()] only flush it. Like
+    /// [`with_output_target()`][Self::with_output_target()], this replaces the target from the
+    /// `NIH_LOG` environment variable.
+    pub fn with_file_handle(mut self, file: File) -> Self {
+        self.output_target = Some(OutputTargetImpl::new_file(file));
+        self
+    }
+
+    /// Change the output target that's used when the `NIH_LOG` environment variable is not set
+    /// and no explicit target was set using [`with_output_target()`][Self::with_output_target()].
+    /// Normally a dynamic target is used in that case that writes to STDERR, or to the Windows
+    /// debugger if one is attached. This can for instance be used to log to a file in the
+    /// application's data directory by default. If the file cannot be opened when the logger is
+    /// installed, then a warning is printed and the dynamic target is used instead.
+    pub fn with_default_target(mut self, target: OutputTarget) -> Self {
+        self.default_target = Some(target);
+        self
+    }
+
+    /// Additionally write records at or above `level` to another output target. For instance,
+    /// `route_level(LevelFilter::Error, OutputTarget::File("errors.log".into()))` mirrors all
+    /// errors to a separate file while all records are still written to the regular output
+    /// target. Can be called multiple times to add more routes. Routed targets are not used for
+    /// reentrant logging calls.
+    ///
+    /// Every target has its own level. The level passed to [`new()`][Self::new()] only applies to
+    /// the regular output target, and a route may be more verbose than that. The logger's maximum
+    /// level is the most verbose level out of all targets. For instance, `new(LevelFilter::Info)`
+    /// combined with `route_level(LevelFilter::Trace, OutputTarget::File("trace.log".into()))`
+    /// writes everything to the file while only showing info messages and above on STDERR.
+    #[allow(clippy::result_large_err)]
+    pub fn route_level(
+        mut self,
+        level: LevelFilter,
+        target: OutputTarget,
+    ) -> Result<Self, SetTargetError> {
+        match open_output_target(target, self.create_log_dirs) {
+            Ok(target) => {
+                self.level_routes
+                    .push((RouteLevels::AtOrAbove(level), target));
+                Ok(self)
+            }
+            Err((path, error)) => Err(SetTargetError::FileOpenError {
+                builder: self,
+                path,
+                error,
+            }),
+        }
+    }
+
+    /// Write every record to a file in `dir` for the record's level, in addition to the regular
+    /// output target. The files are called `error.log`, `warn.log`, `info.log`, `debug.log`, and
+    /// `trace.log`, and each file only contains records of that level. Files are only created for
+    /// the levels enabled by the builder's maximum log level. For a combined file containing all
+    /// records, set the regular output target to a file using
+    /// [`with_output_target()`][Self::with_output_target()]. The directory is only created if
+    /// [`create_log_dirs()`][Self::create_log_dirs()] was called first.
+    #[allow(clippy::result_large_err)]
+    pub fn categorized_files(mut self, dir: PathBuf) -> Result<Self, SetTargetError> {
+        for level in log::Level::iter().filter(|level| *level <= self.max_log_level) {
+            let path = dir.join(format!("{}.log", level.as_str().to_ascii_lowercase()));
+            match open_output_target(OutputTarget::File(path), self.create_log_dirs) {
+                Ok(target) => self
+                    .level_routes
+                    .push((RouteLevels::Exactly(level), target)),
+                Err((path, error)) => {
                     return Err(SetTargetError::FileOpenError {
                         builder: self,
                         path,
                         error,
                     })
                 }
-            },
-        });
+            }
+        }
 
         Ok(self)
     }
 }
+
+/// Create the actual output target for an [`OutputTarget`]. If a file could not be opened, then
+/// the file's path is returned alongside the error.
+pub fn open_output_target(
+    target: OutputTarget,
+    create_dirs: bool,
+) -> Result<OutputTargetImpl, (PathBuf, std::io::Error)> {
+    match target {
+        OutputTarget::Stderr => Ok(OutputTargetImpl::new_stderr()),
+        OutputTarget::RawStderr => Ok(OutputTargetImpl::new_raw_stderr()),
+        #[cfg(windows)]
+        OutputTarget::WinDbg => Ok(OutputTargetImpl::new_windbg()),
+        OutputTarget::File(path) => {
+            OutputTargetImpl::new_file_path(&path, create_dirs).map_err(|error| (path, error))
+        }
+        OutputTarget::CappedFile { path, max_bytes } => {
+            OutputTargetImpl::new_capped_file_path(&path, max_bytes, create_dirs)
+                .map_err(|error| (path, error))
+        }
+        OutputTarget::AtomicFile(path) => {
+            OutputTargetImpl::new_atomic_file_path(&path, create_dirs)
+                .map_err(|error| (path, error))
+        }
+        #[cfg(feature = "flate2")]
+        OutputTarget::GzipFile(path) => {
+            OutputTargetImpl::new_gzip_file_path(&path, create_dirs).map_err(|error| (path, error))
+        }
+        OutputTarget::RingBuffer { size } => Ok(OutputTargetImpl::new_ring_buffer(size)),
+        #[cfg(all(target_arch = "wasm32", feature = "wasm-console"))]
+        OutputTarget::Console => Ok(OutputTargetImpl::new_console()),
+    }
+}
+
+/// Write the session header, replay or discard the records captured since
+/// [`init_early()`][crate::init_early()], and start the flush thread for the newly installed global
+/// logger.
+fn start_global_logger(
+    logger_instance: &'static Logger,
+    flush_interval: Option<Duration>,
+    replay_early_records: bool,
+) {
+    logger_instance.write_session_header();
+    logger_instance.log_env_warnings();
+    early::replay(logger_instance, replay_early_records);
+    if let Some(interval) = flush_interval {
+        logger_instance
+            .flush_thread
+            .spawn(logger_instance, interval);
+    }
+}
+
+/// Finish installing the global logger after it, or a proxy forwarding to it, has been registered
+/// with the `log` crate.
+fn mark_as_log_facade(logger_instance: &'static Logger) {
+    log::set_max_level(logger_instance.facade_max_level());
+    logger_instance.is_log_facade.store(true, Ordering::Relaxed);
+}
+
+/// Parse an `env_logger` style `RUST_LOG` directive string into a default level and a list of
+/// per-module levels. Invalid directives are ignored.
+fn parse_rust_log(spec: &str) -> (LevelFilter, Vec<(String, LevelFilter)>) {
+    // Anything after a slash is a regular expression filter, which we don't support
+    let spec = spec.split('/').next().unwrap_or("");
+
+    let mut default_level = None;
+    let mut module_levels = Vec::new();
+    for directive in spec.split(',').map(str::trim) {
+        if directive.is_empty() {
+            continue;
+        }
+
+        match directive.split_once('=') {
+            Some((name, level)) => {
+                if let Ok(level) = LevelFilter::from_str(level.trim()) {
+                    module_levels.push((name.trim().to_owned(), level));
+                }
+            }
+            // A bare level sets the default level, and a bare module name enables all messages
+            // from that module
+            None => match LevelFilter::from_str(directive) {
+                Ok(level) => default_level = Some(level),
+                Err(_) => module_levels.push((directive.to_owned(), LevelFilter::Trace)),
+            },
+        }
+    }
+
+    // `env_logger` only logs errors when no directives are set, and it doesn't log anything from
+    // non-matching modules if only module directives are set
+    let default_level = default_level.unwrap_or(if module_levels.is_empty() {
+        LevelFilter::Error
+    } else {
+        LevelFilter::Off
+    });
+
+    (default_level, module_levels)
+}
+
+/// The level for `target` according to the directives parsed by [`parse_rust_log()`]. Just like in
+/// `env_logger`, the longest matching module name takes precedence. A module name matches the
+/// module itself and its submodules, so `foo` matches `foo::bar` but not `foobar`.
+fn rust_log_level(
+    target: &str,
+    default_level: LevelFilter,
+    module_levels: &[(String, LevelFilter)],
+) -> LevelFilter {
+    module_levels
+        .iter()
+        .filter(|(name, _)| match target.strip_prefix(name.as_str()) {
+            Some(rest) => rest.is_empty() || rest.starts_with("::"),
+            None => false,
+        })
+        .max_by_key(|(name, _)| name.len())
+        .map(|(_, level)| *level)
+        .unwrap_or(default_level)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::datetime;
+
+    /// Format a fixed date using the builder's custom time format. 2024-12-30 is a Monday in the
+    /// first ISO week of 2025, and it's the 365th day of the year.
+    fn render_time_format(format: &str) -> String {
+        let builder = LoggerBuilder::new(LevelFilter::Info)
+            .with_time_format(format)
+            .unwrap_or_else(|err| panic!("{err}"));
+
+        datetime!(2024-12-30 12:34:56 UTC)
+            .format(builder.time_format.as_ref().unwrap())
+            .unwrap()
+    }
+
+    #[test]
+    fn time_format_iso_week() {
+        assert_eq!(
+            render_time_format("[year repr:full base:iso_week]-W[week_number repr:iso]"),
+            "2025-W01"
+        );
+    }
+
+    #[test]
+    fn time_format_ordinal() {
+        assert_eq!(
+            render_time_format("[year]-[ordinal] [hour]:[minute]"),
+            "2024-365 12:34"
+        );
+    }
+
+    #[test]
+    fn time_format_weekday() {
+        assert_eq!(render_time_format("[weekday repr:long]"), "Monday");
+        assert_eq!(render_time_format("[weekday repr:short]"), "Mon");
+    }
+
+    #[test]
+    fn wrap_at_conflicts() {
+        let builder = || LoggerBuilder::new(LevelFilter::Info).wrap_at(80);
+        assert!(builder().validate().is_ok());
+        assert!(matches!(
+            builder().message_only().validate(),
+            Err(SetLoggerError(SetLoggerErrorKind::ConflictingOptions(
+                "message_only",
+                "wrap_at"
+            )))
+        ));
+        assert!(matches!(
+            builder().with_logfmt().validate(),
+            Err(SetLoggerError(SetLoggerErrorKind::ConflictingOptions(
+                "with_logfmt",
+                "wrap_at"
+            )))
+        ));
+    }
+
+    #[test]
+    fn raw_stderr_with_forced_colors_conflicts() {
+        let builder = |mode| {
+            LoggerBuilder::new(LevelFilter::Info)
+                .with_color_mode(mode)
+                .with_output_target(OutputTarget::RawStderr)
+                .unwrap()
+        };
+        assert!(builder(ColorMode::Never).validate().is_ok());
+        assert!(matches!(
+            builder(ColorMode::Always).validate(),
+            Err(SetLoggerError(SetLoggerErrorKind::RawStderrColors))
+        ));
+
+        let builder = LoggerBuilder::new(LevelFilter::Info).with_color_mode(ColorMode::Always);
+        assert!(builder
+            .with_output_target(OutputTarget::Stderr)
+            .unwrap()
+            .validate()
+            .is_ok());
+    }
+
+    #[test]
+    fn monotonic_timestamps_with_time_format_conflicts() {
+        let builder = LoggerBuilder::new(LevelFilter::Info).with_monotonic_timestamps();
+        assert!(builder.validate().is_ok());
+        assert!(matches!(
+            builder
+                .with_time_format("[hour]:[minute]")
+                .unwrap()
+                .validate(),
+            Err(SetLoggerError(SetLoggerErrorKind::ConflictingOptions(
+                "with_monotonic_timestamps",
+                "with_time_format"
+            )))
+        ));
+        assert!(matches!(
+            LoggerBuilder::new(LevelFilter::Info)
+                .with_time_format_static(crate::time_format!("[hour]"))
+                .with_monotonic_timestamps()
+                .validate(),
+            Err(SetLoggerError(SetLoggerErrorKind::ConflictingOptions(
+                "with_monotonic_timestamps",
+                "with_time_format"
+            )))
+        ));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn same_control_signals() {
+        let builder = LoggerBuilder::new(LevelFilter::Info);
+        assert!(builder
+            .with_signal_control(libc::SIGUSR1, libc::SIGUSR2)
+            .validate()
+            .is_ok());
+
+        let builder = LoggerBuilder::new(LevelFilter::Info);
+        assert!(matches!(
+            builder
+                .with_signal_control(libc::SIGUSR1, libc::SIGUSR1)
+                .validate(),
+            Err(SetLoggerError(SetLoggerErrorKind::SameControlSignals(
+                libc::SIGUSR1
+            )))
+        ));
+    }
+
+    #[test]
+    fn failed_build_leaves_atomic_file_untouched() {
+        let path = std::env::temp_dir().join(format!(
+            "nih-log-test-{}-atomic-failed-build.log",
+            std::process::id()
+        ));
+        std::fs::write(&path, "previous run\n").unwrap();
+
+        let result = LoggerBuilder::new(LevelFilter::Info)
+            .message_only()
+            .with_logfmt()
+            .with_output_target(OutputTarget::AtomicFile(path.clone()))
+            .unwrap()
+            .build();
+        assert!(result.is_err());
+
+        assert_eq!(std::fs::read(&path).unwrap(), b"previous run\n");
+        let mut temp_path = path.clone().into_os_string();
+        temp_path.push(".tmp");
+        assert!(!PathBuf::from(temp_path).exists());
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn deterministic_output() {
+        let (logger, _) = LoggerBuilder::new(LevelFilter::Trace)
+            .deterministic()
+            .on_offset_failure(OffsetFailureAction::SilentUtc)
+            .build()
+            .unwrap();
+        let preview = |level, target, line, message| {
+            logger.format_preview(
+                &log::Record::builder()
+                    .args(format_args!("{message}"))
+                    .level(level)
+                    .target(target)
+                    .module_path_static(Some("my_crate::audio"))
+                    .file_static(Some("src/audio.rs"))
+                    .line(Some(line))
+                    .build(),
+            )
+        };
+
+        let output = [
+            preview(Level::Error, "my_crate::audio", 10, "device lost"),
+            preview(Level::Info, "my_crate::audio", 20, "started"),
+            preview(Level::Debug, "network", 30, "explicit target"),
+            preview(Level::Trace, "my_crate::audio", 40, "buffer filled"),
+        ]
+        .concat();
+        assert_eq!(
+            output,
+            "[ERROR] my_crate::audio: device lost\n\
+             [INFO] my_crate::audio: started\n\
+             [DEBUG] my_crate::audio: explicit target\n\
+             [TRACE] my_crate::audio: [src/audio.rs:40] buffer filled\n"
+        );
+    }
+
+    #[test]
+    fn invalid_time_format() {
+        for format in ["[week_number repr:bogus]", "[not_a_component]", "[hour"] {
+            match LoggerBuilder::new(LevelFilter::Info).with_time_format(format) {
+                Err(SetTimeFormatError::InvalidFormat {
+                    format: error_format,
+                    ..
+                }) => assert_eq!(error_format, format),
+                Ok(_) => panic!("'{format}' should not have been accepted"),
+            }
+        }
+    }
+
+    /// Log a record with the given level and message through `logger`.
+    fn log_to(logger: &Logger, level: Level, message: &str) {
+        log::Log::log(
+            logger,
+            &log::Record::builder()
+                .args(format_args!("{message}"))
+                .level(level)
+                .target("my_crate")
+                .module_path(Some("my_crate"))
+                .build(),
+        );
+    }
+
+    #[test]
+    fn route_level_uses_its_own_level() {
+        let path = std::env::temp_dir().join(format!(
+            "nih-log-test-{}-route-level.log",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+
+        let (logger, _) = LoggerBuilder::new(LevelFilter::Warn)
+            .deterministic()
+            .on_offset_failure(OffsetFailureAction::SilentUtc)
+            .with_output_target(OutputTarget::File(path.clone()))
+            .unwrap()
+            .route_level(LevelFilter::Info, OutputTarget::RingBuffer { size: 4096 })
+            .unwrap()
+            .build()
+            .unwrap();
+        assert_eq!(logger.effective_max_level(), LevelFilter::Info);
+
+        log_to(&logger, Level::Error, "failed");
+        log_to(&logger, Level::Info, "started");
+        log_to(&logger, Level::Debug, "details");
+        log::Log::flush(&logger);
+
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "[ERROR] my_crate: failed\n"
+        );
+        assert_eq!(
+            String::from_utf8(logger.ring_buffer_contents().unwrap()).unwrap(),
+            "[ERROR] my_crate: failed\n[INFO] my_crate: started\n"
+        );
+        drop(logger);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn categorized_files_per_level() {
+        let dir =
+            std::env::temp_dir().join(format!("nih-log-test-{}-categorized", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let (logger, _) = LoggerBuilder::new(LevelFilter::Info)
+            .deterministic()
+            .on_offset_failure(OffsetFailureAction::SilentUtc)
+            .with_output_target(OutputTarget::RingBuffer { size: 4096 })
+            .unwrap()
+            .create_log_dirs()
+            .categorized_files(dir.clone())
+            .unwrap()
+            .build()
+            .unwrap();
+
+        log_to(&logger, Level::Warn, "slow");
+        log_to(&logger, Level::Info, "started");
+        log_to(&logger, Level::Info, "stopped");
+        log_to(&logger, Level::Debug, "details");
+        log::Log::flush(&logger);
+
+        let read = |name| std::fs::read_to_string(dir.join(name)).unwrap();
+        assert_eq!(read("error.log"), "");
+        assert_eq!(read("warn.log"), "[WARN] my_crate: slow\n");
+        assert_eq!(
+            read("info.log"),
+            "[INFO] my_crate: started\n[INFO] my_crate: stopped\n"
+        );
+        assert!(!dir.join("debug.log").exists());
+        assert!(!dir.join("trace.log").exists());
+        assert_eq!(
+            String::from_utf8(logger.ring_buffer_contents().unwrap()).unwrap(),
+            "[WARN] my_crate: slow\n[INFO] my_crate: started\n[INFO] my_crate: stopped\n"
+        );
+        drop(logger);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn parse_rust_log_directives() {
+        assert_eq!(parse_rust_log(""), (LevelFilter::Error, Vec::new()));
+        assert_eq!(parse_rust_log("debug"), (LevelFilter::Debug, Vec::new()));
+        assert_eq!(
+            parse_rust_log("warn, my_crate = debug,other,bogus=loud"),
+            (
+                LevelFilter::Warn,
+                vec![
+                    (String::from("my_crate"), LevelFilter::Debug),
+                    (String::from("other"), LevelFilter::Trace),
+                ]
+            )
+        );
+        assert_eq!(
+            parse_rust_log("my_crate=info/ignored,regex"),
+            (
+                LevelFilter::Off,
+                vec![(String::from("my_crate"), LevelFilter::Info)]
+            )
+        );
+    }
+
+    #[test]
+    fn rust_log_module_matching() {
+        let (default_level, module_levels) = parse_rust_log("warn,foo=debug,foo::bar=trace");
+        let level = |target| rust_log_level(target, default_level, &module_levels);
+
+        assert_eq!(level("foo"), LevelFilter::Debug);
+        assert_eq!(level("foo::baz"), LevelFilter::Debug);
+        assert_eq!(level("foo::bar"), LevelFilter::Trace);
+        assert_eq!(level("foo::bar::baz"), LevelFilter::Trace);
+        assert_eq!(level("foo::barn"), LevelFilter::Debug);
+        assert_eq!(level("foobar"), LevelFilter::Warn);
+        assert_eq!(level("other"), LevelFilter::Warn);
+    }
+}
@@ -0,0 +1,71 @@
+//! Thread-local key-value pairs that are appended to every log line written from the current
+//! thread, similar to Java's mapped diagnostic context.
+
+use std::cell::RefCell;
+use std::marker::PhantomData;
+
+thread_local! {
+    /// The current thread's context. A vector is used instead of a map so the pairs are written
+    /// in insertion order.
+    static CONTEXT: RefCell<Vec<(String, String)>> = const { RefCell::new(Vec::new()) };
+}
+
+/// A handle to the current thread's logging context. Obtained through [`context()`]. Key-value
+/// pairs inserted here are appended to every log line written from this thread until they are
+/// removed again.
+#[derive(Debug)]
+pub struct Context {
+    /// The context is thread-local, so the handle should not be sent to other threads.
+    _not_send: PhantomData<*const ()>,
+}
+
+/// Get a handle to the current thread's logging context. For instance,
+/// `nih_log::context().insert("request_id", id)` causes ` request_id=<id>` to be appended to all
+/// log lines written from the current thread until the key is removed again.
+pub fn context() -> Context {
+    Context {
+        _not_send: PhantomData,
+    }
+}
+
+impl Context {
+    /// Add a key-value pair to the current thread's context. If the key already exists, then its
+    /// value is replaced.
+    pub fn insert(&self, key: impl Into<String>, value: impl ToString) {
+        let key = key.into();
+        let value = value.to_string();
+        CONTEXT.with(|context| {
+            let mut context = context.borrow_mut();
+            match context.iter_mut().find(|(k, _)| *k == key) {
+                Some((_, v)) => *v = value,
+                None => context.push((key, value)),
+            }
+        });
+    }
+
+    /// Remove a key from the current thread's context.
+    pub fn remove(&self, key: &str) {
+        CONTEXT.with(|context| context.borrow_mut().retain(|(k, _)| k != key));
+    }
+
+    /// Remove all key-value pairs from the current thread's context.
+    pub fn clear(&self) {
+        CONTEXT.with(|context| context.borrow_mut().clear());
+    }
+}
+
+/// Call `f` for every key-value pair in `record_fields`, the fields attached to the record that's
+/// being logged, followed by every key-value pair in the current thread's context.
+pub fn for_each_pair(record_fields: &[(&'static str, String)], mut f: impl FnMut(&str, &str)) {
+    for (key, value) in record_fields {
+        f(key, value);
+    }
+    CONTEXT.with(|context| {
+        // The context is never modified while logging, but the logger should never panic here
+        if let Ok(context) = context.try_borrow() {
+            for (key, value) in context.iter() {
+                f(key, value);
+            }
+        }
+    });
+}
@@ -0,0 +1,289 @@
+//! Line-based diffs of before and after values. See [`diff!()`][crate::diff!()].
+
+use std::cell::RefCell;
+
+/// Above this many lines in both values multiplied, not counting the lines they start and end with
+/// in common, the values are not diffed and all old lines are shown as removed followed by all new
+/// lines as added. This keeps the quadratic diffing algorithm from stalling the program or
+/// allocating a large table on the logging thread. With two bytes per cell, the table is at most
+/// 128 KiB.
+const MAX_DIFF_CELLS: usize = 1 << 16;
+
+thread_local! {
+    /// The diff attached to the record that's currently being logged on this thread through
+    /// `diff!()`. This is set right before the record is sent to the logger and removed again
+    /// afterwards.
+    static RECORD_DIFF: RefCell<Vec<(DiffLine, String)>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Log a line-based diff between the pretty-printed [`Debug`][std::fmt::Debug] representations of
+/// two values at the debug level. The label is used as the record's message, and the diff is
+/// written on the lines following it. Removed lines are prefixed with `-` and shown in red, added
+/// lines are prefixed with `+` and shown in green, and unchanged lines are prefixed with two
+/// spaces. Colors are only used when the output target supports them. With
+/// [`LoggerBuilder::with_logfmt()`][crate::LoggerBuilder::with_logfmt()] the diff is written as a
+/// `diff` field instead.
+///
+/// The values are only formatted and diffed if debug records are enabled. The target is optional,
+/// just like with [`log::debug!()`]. For instance,
+/// `nih_log::diff!("parameters changed", old_params, new_params)`.
+#[macro_export]
+macro_rules! diff {
+    (target: $target:expr, $label:expr, $old:expr, $new:expr $(,)?) => {{
+        let level = $crate::__private::log::Level::Debug;
+        if level <= $crate::__private::log::STATIC_MAX_LEVEL
+            && level <= $crate::__private::log::max_level()
+        {
+            let _diff = $crate::__private::RecordDiff::set(
+                &::std::format!("{:#?}", $old),
+                &::std::format!("{:#?}", $new),
+            );
+            $crate::__private::log::log!(target: $target, level, "{}", $label);
+        }
+    }};
+    ($label:expr, $old:expr, $new:expr $(,)?) => {
+        $crate::diff!(target: ::std::module_path!(), $label, $old, $new)
+    };
+}
+
+/// The kind of a line in a diff.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffLine {
+    /// The line is the same in both values.
+    Unchanged,
+    /// The line only occurs in the old value.
+    Removed,
+    /// The line only occurs in the new value.
+    Added,
+}
+
+impl DiffLine {
+    /// The prefix written before lines of this kind.
+    pub fn prefix(self) -> &'static str {
+        match self {
+            DiffLine::Unchanged => "  ",
+            DiffLine::Removed => "- ",
+            DiffLine::Added => "+ ",
+        }
+    }
+}
+
+/// Sets the current thread's record diff while it's alive, and restores the previous diff when
+/// it's dropped. Used by [`diff!()`][crate::diff!()].
+pub struct RecordDiff {
+    previous: Vec<(DiffLine, String)>,
+}
+
+impl RecordDiff {
+    pub fn set(old: &str, new: &str) -> Self {
+        let lines = diff_lines(old, new);
+        // Formatting the label may itself log a diff, so the previous diff is restored instead of
+        // clearing it on drop
+        let previous = RECORD_DIFF
+            .try_with(|record_diff| record_diff.replace(lines))
+            .unwrap_or_default();
+
+        Self { previous }
+    }
+}
+
+impl Drop for RecordDiff {
+    fn drop(&mut self) {
+        let previous = std::mem::take(&mut self.previous);
+        let _ = RECORD_DIFF.try_with(|record_diff| record_diff.replace(previous));
+    }
+}
+
+/// Take the diff attached to the record that's currently being logged on this thread. Like
+/// [`take_fields()`][crate::kv::take_fields()], the logger does this before it formats the
+/// record's message so records logged while formatting the label don't inherit the diff.
+pub fn take_lines() -> Vec<(DiffLine, String)> {
+    RECORD_DIFF
+        .try_with(|record_diff| match record_diff.try_borrow_mut() {
+            Ok(mut record_diff) => std::mem::take(&mut *record_diff),
+            Err(_) => Vec::new(),
+        })
+        .unwrap_or_default()
+}
+
+/// Compute a line-based diff between `old` and `new` using the longest common subsequence of
+/// their lines.
+fn diff_lines(old: &str, new: &str) -> Vec<(DiffLine, String)> {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+
+    // Changes between two `Debug` representations are usually small, so the lines both values
+    // start and end with don't need to go through the table
+    let prefix_len = old
+        .iter()
+        .zip(&new)
+        .take_while(|(old_line, new_line)| old_line == new_line)
+        .count();
+    let suffix_len = old[prefix_len..]
+        .iter()
+        .rev()
+        .zip(new[prefix_len..].iter().rev())
+        .take_while(|(old_line, new_line)| old_line == new_line)
+        .count();
+    let old_changed = &old[prefix_len..old.len() - suffix_len];
+    let new_changed = &new[prefix_len..new.len() - suffix_len];
+
+    let mut lines = Vec::with_capacity(old.len().max(new.len()));
+    let unchanged = |line: &&str| (DiffLine::Unchanged, (*line).to_owned());
+    lines.extend(old[..prefix_len].iter().map(unchanged));
+    diff_changed_lines(old_changed, new_changed, &mut lines);
+    lines.extend(old[old.len() - suffix_len..].iter().map(unchanged));
+
+    lines
+}
+
+/// Append the diff between two sequences of lines to `lines`. Falls back to removing all of `old`
+/// and adding all of `new` when that would need more than [`MAX_DIFF_CELLS`] table cells.
+fn diff_changed_lines(old: &[&str], new: &[&str], lines: &mut Vec<(DiffLine, String)>) {
+    if old.len().saturating_mul(new.len()) > MAX_DIFF_CELLS {
+        lines.extend(
+            old.iter()
+                .map(|line| (DiffLine::Removed, (*line).to_owned())),
+        );
+        lines.extend(new.iter().map(|line| (DiffLine::Added, (*line).to_owned())));
+        return;
+    }
+
+    // `lcs[i * width + j]` is the length of the longest common subsequence of `old[i..]` and
+    // `new[j..]`. The length is at most the square root of `MAX_DIFF_CELLS`, so it fits in a `u16`.
+    let width = new.len() + 1;
+    let mut lcs = vec![0u16; (old.len() + 1) * width];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i * width + j] = if old[i] == new[j] {
+                lcs[(i + 1) * width + j + 1] + 1
+            } else {
+                lcs[(i + 1) * width + j].max(lcs[i * width + j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            lines.push((DiffLine::Unchanged, old[i].to_owned()));
+            i += 1;
+            j += 1;
+        } else if i < old.len()
+            && (j == new.len() || lcs[(i + 1) * width + j] >= lcs[i * width + j + 1])
+        {
+            lines.push((DiffLine::Removed, old[i].to_owned()));
+            i += 1;
+        } else {
+            lines.push((DiffLine::Added, new[j].to_owned()));
+            j += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_diff(old: &str, new: &str, expected: &[(DiffLine, &str)]) {
+        let expected: Vec<(DiffLine, String)> = expected
+            .iter()
+            .map(|(kind, line)| (*kind, (*line).to_owned()))
+            .collect();
+        assert_eq!(diff_lines(old, new), expected);
+    }
+
+    #[test]
+    fn identical_input() {
+        assert_diff(
+            "a\nb\nc",
+            "a\nb\nc",
+            &[
+                (DiffLine::Unchanged, "a"),
+                (DiffLine::Unchanged, "b"),
+                (DiffLine::Unchanged, "c"),
+            ],
+        );
+        assert_diff("", "", &[]);
+    }
+
+    #[test]
+    fn pure_insertion() {
+        assert_diff(
+            "a\nc",
+            "a\nb\nc",
+            &[
+                (DiffLine::Unchanged, "a"),
+                (DiffLine::Added, "b"),
+                (DiffLine::Unchanged, "c"),
+            ],
+        );
+        assert_diff("", "a", &[(DiffLine::Added, "a")]);
+    }
+
+    #[test]
+    fn pure_removal() {
+        assert_diff(
+            "a\nb\nc",
+            "a\nc",
+            &[
+                (DiffLine::Unchanged, "a"),
+                (DiffLine::Removed, "b"),
+                (DiffLine::Unchanged, "c"),
+            ],
+        );
+        assert_diff("a", "", &[(DiffLine::Removed, "a")]);
+    }
+
+    #[test]
+    fn changed_lines_removed_before_added() {
+        assert_diff(
+            "a\nb\nc\nd",
+            "a\nx\nc\ny",
+            &[
+                (DiffLine::Unchanged, "a"),
+                (DiffLine::Removed, "b"),
+                (DiffLine::Added, "x"),
+                (DiffLine::Unchanged, "c"),
+                (DiffLine::Removed, "d"),
+                (DiffLine::Added, "y"),
+            ],
+        );
+    }
+
+    #[test]
+    fn fallback_above_cap() {
+        // The shared first and last lines are still shown as unchanged, but the lines in between
+        // are too many to diff
+        let lines =
+            |prefix: &str| -> Vec<String> { (0..300).map(|i| format!("{prefix}{i}")).collect() };
+        let old = format!("first\n{}\nlast", lines("old").join("\n"));
+        let new = format!("first\n{}\nlast", lines("new").join("\n"));
+        const { assert!(300 * 300 > MAX_DIFF_CELLS) };
+
+        let diff = diff_lines(&old, &new);
+        assert_eq!(diff.len(), 602);
+        assert_eq!(diff[0], (DiffLine::Unchanged, String::from("first")));
+        assert!(diff[1..301]
+            .iter()
+            .zip(lines("old"))
+            .all(|(line, expected)| *line == (DiffLine::Removed, expected)));
+        assert!(diff[301..601]
+            .iter()
+            .zip(lines("new"))
+            .all(|(line, expected)| *line == (DiffLine::Added, expected)));
+        assert_eq!(diff[601], (DiffLine::Unchanged, String::from("last")));
+    }
+
+    #[test]
+    fn large_values_with_small_changes_are_diffed() {
+        let old: Vec<String> = (0..1000).map(|i| i.to_string()).collect();
+        let mut new = old.clone();
+        new[500] = String::from("changed");
+
+        let diff = diff_lines(&old.join("\n"), &new.join("\n"));
+        assert_eq!(diff.len(), 1001);
+        assert_eq!(diff[500], (DiffLine::Removed, String::from("500")));
+        assert_eq!(diff[501], (DiffLine::Added, String::from("changed")));
+    }
+}
@@ -0,0 +1,183 @@
+//! Capturing records that are logged before the global logger is installed. See
+//! [`init_early()`][crate::init_early()].
+
+use log::LevelFilter;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use time::OffsetDateTime;
+
+use crate::logger::{format_message, GlobalLoggerProxy, Logger};
+use crate::LOGGER_INSTANCE;
+
+/// The maximum number of records kept before the logger is installed. Any records logged after
+/// this are dropped, and a warning with the number of dropped records is logged when the buffered
+/// records are replayed.
+const MAX_EARLY_RECORDS: usize = 1024;
+
+/// Whether [`init_early()`] registered the proxy logger with the `log` crate.
+static EARLY_LOGGER_INSTALLED: AtomicBool = AtomicBool::new(false);
+/// The records captured until the global logger is installed.
+static EARLY_RECORDS: Mutex<Vec<EarlyRecord>> = Mutex::new(Vec::new());
+/// The number of records dropped because `EARLY_RECORDS` was full.
+static DROPPED_RECORDS: AtomicUsize = AtomicUsize::new(0);
+
+/// An owned copy of a record that was logged before the global logger was installed.
+struct EarlyRecord {
+    time: OffsetDateTime,
+    level: log::Level,
+    target: String,
+    module_path: Option<String>,
+    file: Option<String>,
+    line: Option<u32>,
+    message: String,
+}
+
+/// Register a lightweight logger with the `log` crate that buffers records until the global logger
+/// is installed. See [`crate::init_early()`].
+pub fn init_early(max_log_level: LevelFilter) -> bool {
+    if LOGGER_INSTANCE.get().is_some() || log::set_logger(&GlobalLoggerProxy).is_err() {
+        return false;
+    }
+
+    EARLY_LOGGER_INSTALLED.store(true, Ordering::Relaxed);
+    log::set_max_level(max_log_level);
+
+    true
+}
+
+/// Whether [`init_early()`] registered NIH-log's proxy logger with the `log` crate. If it did, then
+/// the global logger should not be registered again when it's installed.
+pub fn is_installed() -> bool {
+    EARLY_LOGGER_INSTALLED.load(Ordering::Relaxed)
+}
+
+/// Buffer a record that was logged before the global logger was installed. Does nothing if
+/// [`init_early()`] has not been called.
+pub fn capture(record: &log::Record) {
+    if !is_installed() {
+        return;
+    }
+
+    // The message's `Display` implementations may log themselves, which would deadlock if the
+    // records were already locked
+    let mut message = String::new();
+    format_message(&mut message, record.args());
+
+    let mut records = match EARLY_RECORDS.lock() {
+        Ok(records) => records,
+        Err(err) => err.into_inner(),
+    };
+
+    // The logger may have been installed while this thread was waiting for the lock, in which
+    // case the buffered records have already been replayed
+    if let Some(logger) = LOGGER_INSTANCE.get() {
+        drop(records);
+        logger.log_at(record, None);
+        return;
+    }
+
+    if records.len() >= MAX_EARLY_RECORDS {
+        DROPPED_RECORDS.fetch_add(1, Ordering::Relaxed);
+        return;
+    }
+
+    records.push(EarlyRecord {
+        time: OffsetDateTime::now_utc(),
+        level: record.level(),
+        target: record.target().to_owned(),
+        module_path: record.module_path().map(str::to_owned),
+        file: record.file().map(str::to_owned),
+        line: record.line(),
+        message,
+    });
+}
+
+/// Write the records captured since [`init_early()`] to the newly installed global logger with
+/// their original timestamps, or discard them if `replay` is not set. Any records that are logged
+/// after this are sent to the logger directly.
+pub fn replay(logger: &Logger, replay: bool) {
+    if !is_installed() {
+        return;
+    }
+
+    let mut records = match EARLY_RECORDS.lock() {
+        Ok(records) => records,
+        Err(err) => err.into_inner(),
+    };
+    let dropped_records = DROPPED_RECORDS.swap(0, Ordering::Relaxed);
+    if !replay {
+        records.clear();
+        return;
+    }
+
+    for record in records.drain(..) {
+        logger.log_at(
+            &log::Record::builder()
+                .args(format_args!("{}", record.message))
+                .level(record.level)
+                .target(&record.target)
+                .module_path(record.module_path.as_deref())
+                .file(record.file.as_deref())
+                .line(record.line)
+                .build(),
+            Some(record.time),
+        );
+    }
+
+    if dropped_records > 0 {
+        logger.log_at(
+            &log::Record::builder()
+                .args(format_args!(
+                    "{dropped_records} records logged before the logger was installed were dropped"
+                ))
+                .level(log::Level::Warn)
+                .target(module_path!())
+                .module_path_static(Some(module_path!()))
+                .build(),
+            None,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{LoggerBuilder, OffsetFailureAction, OutputTarget};
+    use log::LevelFilter;
+    use std::fmt::Display;
+
+    /// Logs another record while it's being formatted.
+    struct LogsWhenFormatted;
+
+    impl Display for LogsWhenFormatted {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            log::info!("nested record");
+            write!(f, "formatted")
+        }
+    }
+
+    // This is the only test that installs the global logger, since that can only happen once per
+    // process
+    #[test]
+    fn records_logged_while_capturing_are_kept() {
+        assert!(crate::init_early(LevelFilter::Info));
+        log::info!("outer record {}", LogsWhenFormatted);
+
+        LoggerBuilder::new(LevelFilter::Info)
+            .deterministic()
+            .on_offset_failure(OffsetFailureAction::SilentUtc)
+            .capture_early()
+            .with_output_target(OutputTarget::RingBuffer { size: 4096 })
+            .unwrap()
+            .build_global()
+            .unwrap();
+
+        let contents = String::from_utf8(crate::ring_buffer_contents()).unwrap();
+        assert_eq!(
+            contents,
+            format!(
+                "[INFO] {0}: nested record\n[INFO] {0}: outer record formatted\n",
+                module_path!()
+            )
+        );
+    }
+}
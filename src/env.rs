@@ -0,0 +1,354 @@
+//! Parsing for the `NIH_LOG` environment variable. Check the readme for more information.
+
+use log::LevelFilter;
+use once_cell::sync::OnceCell;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// The environment variable for controlling the logging behavior.
+const NIH_LOG_ENV: &str = "NIH_LOG";
+
+/// The configuration parsed by [`EnvConfig::cached()`].
+static CACHED_ENV_CONFIG: OnceCell<EnvConfig> = OnceCell::new();
+
+/// The configuration parsed from the `NIH_LOG` environment variable. See
+/// [`env_config()`][crate::env_config()].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EnvConfig {
+    /// The raw value of `NIH_LOG`, if it was set.
+    pub raw_value: Option<String>,
+    /// The configuration file `NIH_LOG` pointed to, if its value started with an `@`.
+    pub config_file: Option<PathBuf>,
+    /// The output target requested through `NIH_LOG`.
+    pub target: EnvTarget,
+    /// The maximum log level. Can only be set through a configuration file.
+    pub level: Option<LevelFilter>,
+    /// Problems encountered while parsing the configuration or while opening the requested file,
+    /// along with the fallback that was used. These are written to the log as warnings once the
+    /// logger has been installed.
+    pub warnings: Vec<String>,
+}
+
+/// The output target requested through the `NIH_LOG` environment variable.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EnvTarget {
+    /// `NIH_LOG` was not set, so the default dynamic target should be used.
+    Default,
+    /// Write to STDERR.
+    Stderr,
+    /// Output to the Windows debugger.
+    #[cfg(windows)]
+    WinDbg,
+    /// Write to a file.
+    File(PathBuf),
+}
+
+impl EnvConfig {
+    /// Parse the `NIH_LOG` environment variable. If it starts with an `@`, then the rest of the
+    /// value is treated as a path to a configuration file. Problems with the configuration file
+    /// are recorded in the configuration's warnings.
+    pub fn from_environment() -> Self {
+        let nih_log_env = std::env::var(NIH_LOG_ENV).ok();
+        let nih_log_env_str = nih_log_env.as_deref().unwrap_or("");
+
+        let mut config = match nih_log_env_str.strip_prefix('@') {
+            Some(config_path) => Self::from_config_file(&expand_path(config_path)),
+            None => EnvConfig {
+                raw_value: None,
+                config_file: None,
+                target: parse_target(nih_log_env_str),
+                level: None,
+                warnings: Vec::new(),
+            },
+        };
+        config.raw_value = nih_log_env;
+
+        config
+    }
+
+    /// The same as [`from_environment()`][Self::from_environment()], but the environment is only
+    /// parsed the first time this is called. Targets created for reentrant logging calls use this
+    /// so they don't read the environment variable and configuration file for every record. Only
+    /// the parsed configuration is cached, files are still opened again by the caller.
+    pub(crate) fn cached() -> &'static Self {
+        CACHED_ENV_CONFIG.get_or_init(Self::from_environment)
+    }
+
+    /// The absolute path of the file the log should be written to, if `NIH_LOG` requested a file.
+    /// Relative paths are resolved against the current working directory.
+    pub fn resolved_file_path(&self) -> Option<PathBuf> {
+        match &self.target {
+            EnvTarget::File(path) if path.is_relative() => std::env::current_dir()
+                .map(|dir| dir.join(path))
+                .ok()
+                .or_else(|| Some(path.clone())),
+            EnvTarget::File(path) => Some(path.clone()),
+            _ => None,
+        }
+    }
+
+    /// Record a problem with the configuration. The logger does not yet exist at this point, so
+    /// these are only logged after it has been installed.
+    pub(crate) fn warn(&mut self, warning: String) {
+        self.warnings.push(warning);
+    }
+
+    /// Parse a configuration file. This is a simple line based `key = value` format where empty
+    /// lines and lines starting with `#` are ignored. The supported keys are `target`, which uses
+    /// the same syntax as `NIH_LOG` itself, and `level`. Relative file paths are resolved relative
+    /// to the configuration file's directory.
+    fn from_config_file(path: &Path) -> Self {
+        let mut config = EnvConfig {
+            raw_value: None,
+            config_file: Some(path.to_owned()),
+            target: EnvTarget::Default,
+            level: None,
+            warnings: Vec::new(),
+        };
+
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) => {
+                config.warn(format!(
+                    "Could not read '{}' from NIH_LOG, falling back to the default settings: {err}",
+                    path.display()
+                ));
+                return config;
+            }
+        };
+
+        for (line_idx, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            match line
+                .split_once('=')
+                .map(|(key, value)| (key.trim(), value.trim()))
+            {
+                Some(("target", value)) => {
+                    config.target = match parse_target(value) {
+                        EnvTarget::File(file_path) if file_path.is_relative() => EnvTarget::File(
+                            path.parent()
+                                .map(|dir| dir.join(&file_path))
+                                .unwrap_or(file_path),
+                        ),
+                        target => target,
+                    }
+                }
+                Some(("level", value)) => match LevelFilter::from_str(value) {
+                    Ok(level) => config.level = Some(level),
+                    Err(_) => config.warn(format!(
+                        "Ignoring invalid log level '{value}' on line {} of '{}'",
+                        line_idx + 1,
+                        path.display()
+                    )),
+                },
+                _ => config.warn(format!(
+                    "Ignoring invalid line {} of '{}': {line}",
+                    line_idx + 1,
+                    path.display()
+                )),
+            }
+        }
+
+        config
+    }
+}
+
+/// Parse a target according to the rules in the readme. An empty string results in the default
+/// target.
+fn parse_target(value: &str) -> EnvTarget {
+    if value.is_empty() {
+        return EnvTarget::Default;
+    }
+    if value.eq_ignore_ascii_case("stderr") {
+        return EnvTarget::Stderr;
+    }
+    #[cfg(windows)]
+    if value.eq_ignore_ascii_case("windbg") {
+        return EnvTarget::WinDbg;
+    }
+
+    EnvTarget::File(expand_path(value))
+}
+
+/// Expand a leading `~` to the user's home directory, and expand references to environment
+/// variables written as `$VAR` or `${VAR}`, or as `%VAR%` on Windows. References to variables that
+/// are not set are kept as is.
+fn expand_path(value: &str) -> PathBuf {
+    let mut expanded = String::with_capacity(value.len());
+    let mut remaining = value;
+    if let Some(rest) = remaining.strip_prefix('~') {
+        if rest.is_empty() || rest.starts_with('/') || rest.starts_with(std::path::MAIN_SEPARATOR) {
+            if let Some(home_dir) = home_dir() {
+                expanded.push_str(&home_dir);
+                remaining = rest;
+            }
+        }
+    }
+
+    while let Some(idx) = remaining.find(['$', '%']) {
+        expanded.push_str(&remaining[..idx]);
+        remaining = &remaining[idx..];
+
+        // This is either `${VAR}`, `$VAR`, or `%VAR%`, and the (name, length) of the reference
+        let reference = if let Some(rest) = remaining.strip_prefix("${") {
+            rest.find('}').map(|end| (&rest[..end], end + 3))
+        } else if let Some(rest) = remaining.strip_prefix('$') {
+            let end = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            Some((&rest[..end], end + 1))
+        } else if cfg!(windows) {
+            remaining[1..]
+                .find('%')
+                .map(|end| (&remaining[1..end + 1], end + 2))
+        } else {
+            None
+        };
+
+        match reference {
+            Some((name, len)) if !name.is_empty() => {
+                match std::env::var(name) {
+                    Ok(value) => expanded.push_str(&value),
+                    Err(_) => expanded.push_str(&remaining[..len]),
+                }
+                remaining = &remaining[len..];
+            }
+            _ => {
+                expanded.push_str(&remaining[..1]);
+                remaining = &remaining[1..];
+            }
+        }
+    }
+    expanded.push_str(remaining);
+
+    PathBuf::from(expanded)
+}
+
+/// The current user's home directory, if it can be determined.
+fn home_dir() -> Option<String> {
+    #[cfg(windows)]
+    let home_dir = std::env::var("USERPROFILE");
+    #[cfg(not(windows))]
+    let home_dir = std::env::var("HOME");
+
+    home_dir.ok().filter(|dir| !dir.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn config_file() {
+        let dir = std::env::temp_dir().join(format!("nih-log-test-{}-config", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("nih-log.conf");
+        std::fs::write(
+            &path,
+            "# Logging settings\n\
+             \n\
+             target = logs/app.log\n\
+             level = loud\n\
+             level = debug\n\
+             colors = never\n\
+             not a setting\n",
+        )
+        .unwrap();
+
+        let config = EnvConfig::from_config_file(&path);
+        assert_eq!(config.config_file.as_deref(), Some(path.as_path()));
+        assert_eq!(config.target, EnvTarget::File(dir.join("logs/app.log")));
+        assert_eq!(config.level, Some(LevelFilter::Debug));
+        assert_eq!(
+            config.warnings,
+            [
+                format!(
+                    "Ignoring invalid log level 'loud' on line 4 of '{}'",
+                    path.display()
+                ),
+                format!(
+                    "Ignoring invalid line 6 of '{}': colors = never",
+                    path.display()
+                ),
+                format!(
+                    "Ignoring invalid line 7 of '{}': not a setting",
+                    path.display()
+                ),
+            ]
+        );
+
+        std::fs::write(&path, "target = stderr\n").unwrap();
+        let config = EnvConfig::from_config_file(&path);
+        assert_eq!(config.target, EnvTarget::Stderr);
+        assert_eq!(config.level, None);
+        assert!(config.warnings.is_empty());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn missing_config_file() {
+        let path =
+            std::env::temp_dir().join(format!("nih-log-test-{}-missing.conf", std::process::id()));
+        let config = EnvConfig::from_config_file(&path);
+        assert_eq!(config.target, EnvTarget::Default);
+        assert_eq!(config.warnings.len(), 1);
+        assert!(config.warnings[0].starts_with(&format!("Could not read '{}'", path.display())));
+    }
+
+    #[test]
+    fn expand_path_variables() {
+        std::env::set_var("NIH_LOG_TEST_EXPAND_DIR", "/var/log");
+        std::env::remove_var("NIH_LOG_TEST_EXPAND_UNSET");
+
+        let expand = |value| expand_path(value).into_os_string().into_string().unwrap();
+        assert_eq!(
+            expand("$NIH_LOG_TEST_EXPAND_DIR/app.log"),
+            "/var/log/app.log"
+        );
+        assert_eq!(
+            expand("${NIH_LOG_TEST_EXPAND_DIR}app.log"),
+            "/var/logapp.log"
+        );
+        assert_eq!(
+            expand("$NIH_LOG_TEST_EXPAND_UNSET/${NIH_LOG_TEST_EXPAND_UNSET}/app.log"),
+            "$NIH_LOG_TEST_EXPAND_UNSET/${NIH_LOG_TEST_EXPAND_UNSET}/app.log"
+        );
+        assert_eq!(expand("cost$/${unclosed/$"), "cost$/${unclosed/$");
+
+        if cfg!(windows) {
+            assert_eq!(
+                expand("%NIH_LOG_TEST_EXPAND_DIR%/app.log"),
+                "/var/log/app.log"
+            );
+        } else {
+            assert_eq!(
+                expand("%NIH_LOG_TEST_EXPAND_DIR%/app.log"),
+                "%NIH_LOG_TEST_EXPAND_DIR%/app.log"
+            );
+        }
+    }
+
+    #[test]
+    fn expand_path_home_dir() {
+        let Some(home_dir) = home_dir() else {
+            return;
+        };
+
+        assert_eq!(expand_path("~"), PathBuf::from(&home_dir));
+        assert_eq!(
+            expand_path("~/logs/app.log"),
+            PathBuf::from(format!("{home_dir}/logs/app.log"))
+        );
+        // Only a `~` on its own is expanded, not `~user` or a `~` later in the path
+        assert_eq!(expand_path("~user/app.log"), PathBuf::from("~user/app.log"));
+        assert_eq!(
+            expand_path("logs/~/app.log"),
+            PathBuf::from("logs/~/app.log")
+        );
+    }
+}
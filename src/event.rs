@@ -0,0 +1,148 @@
+//! A structured representation of a log record, for building custom sinks.
+
+use log::Level;
+use time::OffsetDateTime;
+
+use crate::logger::{format_message, thread_id};
+use crate::LOGGER_INSTANCE;
+
+/// All of the information NIH-log writes for a log record, extracted into a single struct. This can
+/// be used to implement custom sinks, for instance through
+/// [`LoggerBuilder::chain_to()`][crate::LoggerBuilder::chain_to()], without having to reimplement
+/// the field extraction from a [`log::Record`].
+///
+/// When the `serde` feature is enabled, this implements `serde::Serialize`. The time is then
+/// serialized as an RFC 3339 string and the level as an uppercase string like `"WARN"`. When the
+/// `opentelemetry` feature is enabled, this exposes the information needed to build an
+/// OpenTelemetry `LogRecord`, such as [`otel_severity_number()`][Self::otel_severity_number()]
+/// and [`otel_attributes()`][Self::otel_attributes()].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogEvent<'a> {
+    /// The time the event was created at, in the logger's local time offset.
+    pub time: OffsetDateTime,
+    /// The record's log level.
+    pub level: Level,
+    /// The record's target. This is the module path unless it was explicitly overridden.
+    pub target: &'a str,
+    /// The module path the record was created in.
+    pub module_path: Option<&'a str>,
+    /// The ID of the thread the event was created on.
+    pub thread_id: String,
+    /// The name of the thread the event was created on, if it has a name.
+    pub thread_name: Option<String>,
+    /// The source file the record was created in.
+    pub file: Option<&'a str>,
+    /// The line in `file` the record was created on.
+    pub line: Option<u32>,
+    /// The formatted log message.
+    pub message: String,
+}
+
+impl<'a> LogEvent<'a> {
+    /// Extract the information from a record. This should be called on the thread that created
+    /// the record. If the global logger has been installed, then its local time offset is used for
+    /// the time. Otherwise the time is in UTC.
+    pub fn from_record(record: &log::Record<'a>) -> Self {
+        let local_time_offset = LOGGER_INSTANCE
+            .get()
+            .map(|logger| logger.local_time_offset)
+            .unwrap_or(time::UtcOffset::UTC);
+        let current_thread = std::thread::current();
+        let mut message = String::new();
+        format_message(&mut message, record.args());
+
+        Self {
+            time: OffsetDateTime::now_utc().to_offset(local_time_offset),
+            level: record.level(),
+            target: record.target(),
+            module_path: record.module_path(),
+            thread_id: thread_id(&current_thread),
+            thread_name: current_thread.name().map(str::to_owned),
+            file: record.file(),
+            line: record.line(),
+            message,
+        }
+    }
+}
+
+/// The value of an OpenTelemetry attribute. See [`LogEvent::otel_attributes()`].
+#[cfg(feature = "opentelemetry")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OtelValue<'a> {
+    String(&'a str),
+    Int(i64),
+}
+
+#[cfg(feature = "opentelemetry")]
+impl<'a> LogEvent<'a> {
+    /// The OpenTelemetry severity number for the event's level. This is the lowest number in the
+    /// level's range, so `TRACE` is 1, `DEBUG` is 5, `INFO` is 9, `WARN` is 13, and `ERROR` is 17.
+    pub fn otel_severity_number(&self) -> u8 {
+        match self.level {
+            Level::Trace => 1,
+            Level::Debug => 5,
+            Level::Info => 9,
+            Level::Warn => 13,
+            Level::Error => 17,
+        }
+    }
+
+    /// The OpenTelemetry severity text for the event's level, like `"WARN"`.
+    pub fn otel_severity_text(&self) -> &'static str {
+        self.level.as_str()
+    }
+
+    /// The event's source information as OpenTelemetry attributes using the `code.*` and
+    /// `thread.*` semantic conventions. The module path is written as `code.namespace`, and the
+    /// record's target is written as `log.target` when it differs from the module path. Missing
+    /// information is omitted. The message should be used as the record's body.
+    pub fn otel_attributes(&self) -> Vec<(&'static str, OtelValue<'_>)> {
+        let mut attributes = Vec::with_capacity(6);
+        if let Some(module_path) = self.module_path {
+            attributes.push(("code.namespace", OtelValue::String(module_path)));
+        }
+        if self.module_path != Some(self.target) {
+            attributes.push(("log.target", OtelValue::String(self.target)));
+        }
+        if let Some(file) = self.file {
+            attributes.push(("code.filepath", OtelValue::String(file)));
+        }
+        if let Some(line) = self.line {
+            attributes.push(("code.lineno", OtelValue::Int(line as i64)));
+        }
+        // `thread.id` is an integer in the semantic conventions
+        match self.thread_id.parse() {
+            Ok(thread_id) => attributes.push(("thread.id", OtelValue::Int(thread_id))),
+            Err(_) => attributes.push(("thread.id", OtelValue::String(&self.thread_id))),
+        }
+        if let Some(thread_name) = &self.thread_name {
+            attributes.push(("thread.name", OtelValue::String(thread_name)));
+        }
+
+        attributes
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for LogEvent<'_> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::{Error, SerializeStruct};
+
+        let time = self
+            .time
+            .format(&time::format_description::well_known::Rfc3339)
+            .map_err(S::Error::custom)?;
+
+        let mut event = serializer.serialize_struct("LogEvent", 9)?;
+        event.serialize_field("time", &time)?;
+        event.serialize_field("level", self.level.as_str())?;
+        event.serialize_field("target", self.target)?;
+        event.serialize_field("module_path", &self.module_path)?;
+        event.serialize_field("thread_id", &self.thread_id)?;
+        event.serialize_field("thread_name", &self.thread_name)?;
+        event.serialize_field("file", &self.file)?;
+        event.serialize_field("line", &self.line)?;
+        event.serialize_field("message", &self.message)?;
+        event.end()
+    }
+}
@@ -0,0 +1,158 @@
+//! Key-value pairs attached to a single log record. See [`kv_log!()`][crate::kv_log!()].
+
+use std::cell::RefCell;
+
+thread_local! {
+    /// The fields of the record that's currently being logged on this thread through
+    /// `kv_log!()`. These are set right before the record is sent to the logger and removed again
+    /// afterwards.
+    static RECORD_FIELDS: RefCell<Vec<(&'static str, String)>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Log a message with additional key-value pairs. The pairs are written after the message like the
+/// pairs from the thread's [`context()`][crate::context()], so they become separate fields when
+/// using [`LoggerBuilder::with_logfmt()`][crate::LoggerBuilder::with_logfmt()]. This works without
+/// the `log` crate's unstable key-value support. The values can be anything that implements
+/// [`Display`][std::fmt::Display], and they're only formatted if the record's level is enabled.
+///
+/// The fields are passed in braces between the level and the message, and the message accepts the
+/// same arguments as [`log::log!()`]. The target is optional. [`kv_error!()`][crate::kv_error!()]
+/// through [`kv_trace!()`][crate::kv_trace!()] are shorthands that don't take a level. For
+/// instance, `nih_log::kv_info!(target: "net", { conn_id = 5, bytes = 1024 }, "transfer complete")`
+/// writes `transfer complete conn_id=5 bytes=1024`.
+#[macro_export]
+macro_rules! kv_log {
+    (target: $target:expr, $level:expr, { $($key:ident = $value:expr),* $(,)? }, $($arg:tt)+) => {{
+        let level = $level;
+        if level <= $crate::__private::log::STATIC_MAX_LEVEL
+            && level <= $crate::__private::log::max_level()
+        {
+            let _fields = $crate::__private::RecordFields::set(::std::vec![
+                $((::std::stringify!($key), ::std::string::ToString::to_string(&$value))),*
+            ]);
+            $crate::__private::log::log!(target: $target, level, $($arg)+);
+        }
+    }};
+    ($level:expr, { $($fields:tt)* }, $($arg:tt)+) => {
+        $crate::kv_log!(target: ::std::module_path!(), $level, { $($fields)* }, $($arg)+)
+    };
+}
+
+/// Log an error with additional key-value pairs. See [`kv_log!()`][crate::kv_log!()].
+#[macro_export]
+macro_rules! kv_error {
+    (target: $target:expr, $($arg:tt)+) => {
+        $crate::kv_log!(target: $target, $crate::__private::log::Level::Error, $($arg)+)
+    };
+    ($($arg:tt)+) => {
+        $crate::kv_log!($crate::__private::log::Level::Error, $($arg)+)
+    };
+}
+
+/// Log a warning with additional key-value pairs. See [`kv_log!()`][crate::kv_log!()].
+#[macro_export]
+macro_rules! kv_warn {
+    (target: $target:expr, $($arg:tt)+) => {
+        $crate::kv_log!(target: $target, $crate::__private::log::Level::Warn, $($arg)+)
+    };
+    ($($arg:tt)+) => {
+        $crate::kv_log!($crate::__private::log::Level::Warn, $($arg)+)
+    };
+}
+
+/// Log an info message with additional key-value pairs. See [`kv_log!()`][crate::kv_log!()].
+#[macro_export]
+macro_rules! kv_info {
+    (target: $target:expr, $($arg:tt)+) => {
+        $crate::kv_log!(target: $target, $crate::__private::log::Level::Info, $($arg)+)
+    };
+    ($($arg:tt)+) => {
+        $crate::kv_log!($crate::__private::log::Level::Info, $($arg)+)
+    };
+}
+
+/// Log a debug message with additional key-value pairs. See [`kv_log!()`][crate::kv_log!()].
+#[macro_export]
+macro_rules! kv_debug {
+    (target: $target:expr, $($arg:tt)+) => {
+        $crate::kv_log!(target: $target, $crate::__private::log::Level::Debug, $($arg)+)
+    };
+    ($($arg:tt)+) => {
+        $crate::kv_log!($crate::__private::log::Level::Debug, $($arg)+)
+    };
+}
+
+/// Log a trace message with additional key-value pairs. See [`kv_log!()`][crate::kv_log!()].
+#[macro_export]
+macro_rules! kv_trace {
+    (target: $target:expr, $($arg:tt)+) => {
+        $crate::kv_log!(target: $target, $crate::__private::log::Level::Trace, $($arg)+)
+    };
+    ($($arg:tt)+) => {
+        $crate::kv_log!($crate::__private::log::Level::Trace, $($arg)+)
+    };
+}
+
+/// Sets the current thread's record fields while it's alive, and restores the previous fields
+/// when it's dropped. Used by [`kv_log!()`][crate::kv_log!()].
+pub struct RecordFields {
+    previous: Vec<(&'static str, String)>,
+}
+
+impl RecordFields {
+    pub fn set(fields: Vec<(&'static str, String)>) -> Self {
+        // Formatting the message may itself log with fields, so the previous fields are restored
+        // instead of clearing them on drop
+        let previous = RECORD_FIELDS
+            .try_with(|record_fields| record_fields.replace(fields))
+            .unwrap_or_default();
+
+        Self { previous }
+    }
+}
+
+impl Drop for RecordFields {
+    fn drop(&mut self) {
+        let previous = std::mem::take(&mut self.previous);
+        let _ = RECORD_FIELDS.try_with(|record_fields| record_fields.replace(previous));
+    }
+}
+
+/// Take the fields of the record that's currently being logged on this thread. The logger does
+/// this before it formats the record's message, so records logged while formatting the message,
+/// for instance from a `Display` implementation, don't inherit this record's fields.
+pub fn take_fields() -> Vec<(&'static str, String)> {
+    RECORD_FIELDS
+        .try_with(|record_fields| match record_fields.try_borrow_mut() {
+            Ok(mut record_fields) => std::mem::take(&mut *record_fields),
+            Err(_) => Vec::new(),
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn taken_fields_are_not_seen_by_nested_records() {
+        let _fields = RecordFields::set(vec![("outer", String::from("1"))]);
+        assert_eq!(take_fields(), [("outer", String::from("1"))]);
+
+        // A record logged while formatting the outer record's message doesn't see its fields
+        assert!(take_fields().is_empty());
+    }
+
+    #[test]
+    fn previous_fields_restored_on_drop() {
+        let outer = RecordFields::set(vec![("outer", String::from("1"))]);
+        {
+            let _inner = RecordFields::set(vec![("inner", String::from("2"))]);
+            assert_eq!(take_fields(), [("inner", String::from("2"))]);
+        }
+        assert_eq!(take_fields(), [("outer", String::from("1"))]);
+
+        drop(outer);
+        assert!(take_fields().is_empty());
+    }
+}
@@ -1,11 +1,280 @@
+use log::{Level, LevelFilter, Log};
 use once_cell::sync::OnceCell;
+use std::sync::atomic::Ordering;
 
 mod builder;
+mod context;
+mod diff;
+mod early;
+mod env;
+mod event;
+mod kv;
 mod logger;
+#[cfg(unix)]
+mod signal;
+mod snapshot;
 mod target;
+mod theme;
+mod throttle;
+mod writer;
 
-pub use builder::{LoggerBuilder, OutputTarget, SetLoggerError, SetTargetError};
+pub use builder::{
+    ColorMode, LoggerBuilder, OffsetFailureAction, OutputTarget, SetLoggerError, SetTargetError,
+    SetTimeFormatError, ThreadFormat, FEATURE_MAX_LEVEL,
+};
+pub use context::{context, Context};
+pub use env::{EnvConfig, EnvTarget};
+pub use event::LogEvent;
+#[cfg(feature = "opentelemetry")]
+pub use event::OtelValue;
+pub use snapshot::ConfigSnapshot;
+pub use theme::{Color, Theme};
+pub use writer::{writer_for, LogWriter};
+
+/// Implementation details used by the macros. Not part of the public API.
+#[doc(hidden)]
+pub mod __private {
+    pub use crate::diff::RecordDiff;
+    pub use crate::kv::RecordFields;
+    pub use crate::throttle::throttle_call_site;
+    pub use log;
+    pub use time;
+}
+
+/// Parse a timestamp format description at compile time for use with
+/// [`LoggerBuilder::with_time_format_static()`]. This accepts the same version 2 format description
+/// syntax as [`LoggerBuilder::with_time_format()`], for instance
+/// `nih_log::time_format!("[hour]:[minute]:[second].[subsecond digits:3]")`. This expands to the
+/// `time` crate's `format_description!()` macro, which refers to the `time` crate by name. The
+/// crate using this macro thus needs to depend on the `time` crate as well.
+#[macro_export]
+macro_rules! time_format {
+    ($format:tt) => {
+        $crate::__private::time::macros::format_description!(version = 2, $format)
+    };
+}
 
 /// The current logger instance. Initialized in [`LoggerBuilder::build_global()`] and then set as
 /// the global logger using [`log::set_logger()`].
 static LOGGER_INSTANCE: OnceCell<logger::Logger> = OnceCell::new();
+
+/// Register a lightweight logger with the `log` crate that buffers every record up to
+/// `max_log_level` until NIH-log's global logger is installed. This can be called at the very start
+/// of `main()` so records logged while the application and its libraries are still being
+/// configured are not lost. The buffered records are written when the logger is installed with
+/// [`LoggerBuilder::capture_early()`], and discarded otherwise. The global logger must then be
+/// installed with [`LoggerBuilder::build_global()`] or
+/// [`LoggerBuilder::build_global_if_none()`], which reuse the registration from this function.
+///
+/// Returns `false` and does nothing if the global logger has already been installed or another
+/// logger has already been registered with the `log` crate.
+pub fn init_early(max_log_level: LevelFilter) -> bool {
+    early::init_early(max_log_level)
+}
+
+/// The total number of bytes successfully written by the global logger so far. Returns 0 if the
+/// logger has not yet been installed.
+pub fn bytes_written() -> u64 {
+    LOGGER_INSTANCE
+        .get()
+        .map(|logger| logger.bytes_written.load(Ordering::Relaxed))
+        .unwrap_or(0)
+}
+
+/// Send a record directly to NIH-log's global logger, bypassing the `log` crate's global logger.
+/// This can be used when another logger was already registered with the `log` crate when
+/// [`LoggerBuilder::build_global()`] was called. Does nothing if the logger has not yet been
+/// installed.
+pub fn log_record(record: &log::Record) {
+    if let Some(logger) = LOGGER_INSTANCE.get() {
+        // The `log` crate's macros normally check the maximum level before the record is created.
+        // The logger checks it again, since records above the level may still end up in the error
+        // context ring.
+        logger.log(record);
+    }
+}
+
+/// Temporarily stop logging until [`resume()`] is called. While the logger is paused, all records
+/// are dropped before they are formatted, including records that would otherwise be forwarded to a
+/// chained logger. This is intended for short windows like benchmarks or realtime sections, and
+/// it's cheaper than changing the log level. Does nothing if the logger has not yet been installed.
+pub fn pause() {
+    if let Some(logger) = LOGGER_INSTANCE.get() {
+        logger.paused.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Resume logging after a call to [`pause()`].
+pub fn resume() {
+    if let Some(logger) = LOGGER_INSTANCE.get() {
+        logger.paused.store(false, Ordering::Relaxed);
+    }
+}
+
+/// Enable or disable quiet mode at runtime. In quiet mode only warnings and errors are logged. See
+/// [`LoggerBuilder::quiet()`] for more information. If this changes the log level, then a line
+/// noting the change is written unless [`LoggerBuilder::without_level_change_markers()`] was used.
+/// Does nothing if the logger has not yet been installed.
+pub fn set_quiet(quiet: bool) {
+    if let Some(logger) = LOGGER_INSTANCE.get() {
+        let previous_level = logger.effective_max_level();
+        logger.quiet.store(quiet, Ordering::Relaxed);
+        let new_level = logger.effective_max_level();
+        if logger.is_log_facade.load(Ordering::Relaxed) {
+            log::set_max_level(logger.facade_max_level());
+        }
+
+        logger.log_level_change(previous_level, new_level);
+    }
+}
+
+/// Flush and reopen the log file, for instance after an external log rotation tool moved it or
+/// in response to a `SIGHUP` signal. This also applies to files added through
+/// [`LoggerBuilder::route_level()`]. The session header and byte order mark are written to the new
+/// files if those are enabled. Does nothing and returns `Ok(())` if the logger doesn't write to a
+/// file or if it has not yet been installed.
+pub fn rotate_file() -> std::io::Result<()> {
+    match LOGGER_INSTANCE.get() {
+        Some(logger) => logger.rotate_files(),
+        None => Ok(()),
+    }
+}
+
+/// Format a synthetic record using the global logger's configuration without writing it to any
+/// output target, for instance to preview the log format in a settings UI. `target` is used as both
+/// the record's target and its module path. The result includes the trailing newline and never
+/// contains colors. The record is formatted even if it would normally be filtered out. Returns an
+/// empty string if the logger has not yet been installed.
+pub fn format_preview(level: Level, target: &str, message: &str) -> String {
+    match LOGGER_INSTANCE.get() {
+        Some(logger) => logger.format_preview(
+            &log::Record::builder()
+                .args(format_args!("{message}"))
+                .level(level)
+                .target(target)
+                .module_path(Some(target))
+                .build(),
+        ),
+        None => String::new(),
+    }
+}
+
+/// Make records logged from the current thread go to `target` instead of the logger's output
+/// target. Records are still also written to the targets added through
+/// [`LoggerBuilder::route_level()`]. This is useful when a process runs multiple isolated engines
+/// on their own threads. The logger's color and buffering settings are applied to the target, so
+/// this should be called after the logger has been installed. Returns an error if the target is a
+/// file that could not be opened, in which case the thread's previous target is kept.
+///
+/// [`flush()`][log::Log::flush()] and [`shutdown()`] only flush the calling thread's target. Other
+/// threads' targets are flushed when the thread exits or when the target is replaced or cleared.
+pub fn set_thread_target(target: OutputTarget) -> std::io::Result<()> {
+    let logger = LOGGER_INSTANCE.get();
+    let create_dirs = logger.map(|logger| logger.create_log_dirs).unwrap_or(false);
+    let target = builder::open_output_target(target, create_dirs).map_err(|(_, error)| error)?;
+    let target = match logger {
+        Some(logger) => logger.configure_target(target),
+        None => target,
+    };
+    logger::replace_thread_target(Some(target));
+
+    Ok(())
+}
+
+/// Make the current thread use the logger's regular output target again after
+/// [`set_thread_target()`]. The thread's own target is flushed and closed. Does nothing if no target
+/// was set for this thread.
+pub fn clear_thread_target() {
+    if let Some(mut target) = logger::replace_thread_target(None) {
+        let _ = target.writer().flush();
+    }
+}
+
+/// Get the contents of the logger's [`OutputTarget::RingBuffer`], from oldest to newest. If the
+/// buffer has wrapped around, then the first line is likely incomplete. If the ring buffer is used
+/// through [`LoggerBuilder::route_level()`], then that buffer is returned instead. Returns an empty
+/// vector if the logger has not been installed or if it doesn't use a ring buffer.
+pub fn ring_buffer_contents() -> Vec<u8> {
+    LOGGER_INSTANCE
+        .get()
+        .and_then(|logger| logger.ring_buffer_contents())
+        .unwrap_or_default()
+}
+
+/// Flush all buffered output before the process exits. Records are currently always written
+/// synchronously from the thread that logged them, so this flushes the output target and the
+/// chained logger, if any. This also stops the thread started by
+/// [`LoggerBuilder::with_flush_interval()`]. Call this from graceful exit paths so no buffered
+/// output is lost. If [`LoggerBuilder::with_session_footer()`] was used, then the session footer is
+/// written to file targets the first time this is called. Gzip compressed files are finished by
+/// writing the gzip trailer, and files written through [`OutputTarget::AtomicFile`] are renamed to
+/// their destination path. Does nothing if the logger has not yet been installed.
+pub fn shutdown() {
+    if let Some(logger) = LOGGER_INSTANCE.get() {
+        logger.flush_thread.stop();
+        logger.write_session_footer();
+        logger.flush();
+        logger.finish_files();
+    }
+}
+
+/// Flush all buffered output of NIH-log's global logger, including the output of the chained
+/// logger, if any. Unlike [`log::logger().flush()`][log::Log::flush()], this also works when
+/// another logger was registered with the `log` crate. Does nothing if the logger has not yet been
+/// installed.
+pub fn flush() {
+    if let Some(logger) = LOGGER_INSTANCE.get() {
+        logger.flush();
+    }
+}
+
+/// Prepare the logger for forking the process. Call this in the parent right before calling
+/// `fork()`. Output that's still buffered when the process forks would otherwise be written by
+/// both the parent and the child, so this flushes all output targets like [`flush()`]. If
+/// [`LoggerBuilder::close_files_before_fork()`] was used, then log files are also closed so the
+/// child doesn't inherit their file descriptors. They are reopened automatically the next time
+/// something is logged. Thread targets set through [`set_thread_target()`] are only flushed for
+/// the calling thread, and records logged by other threads while the process forks may still end
+/// up in both processes. Does nothing if the logger has not yet been installed.
+pub fn prepare_for_fork() {
+    if let Some(logger) = LOGGER_INSTANCE.get() {
+        logger.prepare_for_fork();
+    }
+}
+
+/// Whether a Windows debugger is currently attached to the process. This is the same check the
+/// default output target uses to decide whether to write to the Windows debugger. Always returns
+/// `false` on non-Windows platforms.
+pub fn debugger_attached() -> bool {
+    target::debugger_attached()
+}
+
+/// Get the parsed `NIH_LOG` configuration. This can be used to print where the log is being written
+/// to, or to find out why the log ended up somewhere unexpected. If the logger has been installed,
+/// then this returns the configuration the logger was built with, including any problems that
+/// occurred while opening the requested file. Otherwise the environment is parsed again.
+pub fn env_config() -> EnvConfig {
+    match LOGGER_INSTANCE.get() {
+        Some(logger) => logger.env_config.clone(),
+        None => EnvConfig::from_environment(),
+    }
+}
+
+/// Take a snapshot of the global logger's current configuration, like its log level, module
+/// blacklist, and output target. This is useful for finding out why the log looks the way it does,
+/// and the snapshot's `Debug` representation can be pasted into bug reports. Returns `None` if the
+/// logger has not yet been installed.
+pub fn config_snapshot() -> Option<ConfigSnapshot> {
+    LOGGER_INSTANCE.get().map(|logger| logger.config_snapshot())
+}
+
+/// The global logger's effective maximum log level. This takes the log level set through a
+/// `NIH_LOG` configuration file, quiet mode, and the `log` crate's static maximum log level into
+/// account. Unlike [`log::max_level()`], this is not affected by other loggers. Returns
+/// [`LevelFilter::Off`] if the logger has not yet been installed.
+pub fn max_level() -> LevelFilter {
+    LOGGER_INSTANCE
+        .get()
+        .map(|logger| logger.effective_max_level())
+        .unwrap_or(LevelFilter::Off)
+}
@@ -2,43 +2,672 @@
 //! API.
 
 use log::{Level, LevelFilter, Log};
-use std::cell::Cell;
-use std::collections::HashSet;
-use std::sync::Mutex;
-use termcolor::Color;
+use std::backtrace::{Backtrace, BacktraceStatus};
+use std::cell::{Cell, RefCell};
+use std::collections::{HashSet, VecDeque};
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, AtomicIsize, AtomicU64, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use termcolor::{Ansi, Color, ColorSpec, WriteColor};
+use time::format_description::well_known::Rfc3339;
+use time::format_description::OwnedFormatItem;
 use time::UtcOffset;
 
-use crate::target::{OutputTargetImpl, WriteExt};
+use crate::builder::{open_output_target, ColorMode, OutputTarget, ThreadFormat};
+use crate::context;
+use crate::diff::{self, DiffLine};
+use crate::env::EnvConfig;
+use crate::kv;
+use crate::snapshot::ConfigSnapshot;
+use crate::target::{CountingWriter, OutputTargetImpl, WriteExt};
+use crate::theme::Theme;
 
 /// The formatting description for times. Each log message is prefixed by the current time as
 /// `hh:mm:ss`.
 const TIME_FORMAT_DESCRIPTION: &[time::format_description::FormatItem] =
     time::macros::format_description!("[hour]:[minute]:[second]");
 
+/// The marker written before messages from highlighted crates. See
+/// [`LoggerBuilder::highlight_crate()`][crate::LoggerBuilder::highlight_crate()].
+const HIGHLIGHT_MARKER: &str = "▸ ";
+
+/// The formatting description for the date and time in the session header.
+const SESSION_TIME_FORMAT_DESCRIPTION: &[time::format_description::FormatItem] =
+    time::macros::format_description!("[year]-[month]-[day] [hour]:[minute]:[second]");
+
+/// Written after the part of a message that was already formatted when one of the message's
+/// `Display` implementations returned an error.
+const MESSAGE_FORMATTING_FAILED: &str = "<message formatting failed>";
+
+/// A user provided filter function. See
+/// [`LoggerBuilder::with_filter_fn()`][crate::LoggerBuilder::with_filter_fn()].
+pub type FilterFn = Box<dyn Fn(&log::Metadata) -> bool + Send + Sync>;
+
+/// A user provided function that's called for every record that passes the filters. See
+/// [`LoggerBuilder::with_record_hook()`][crate::LoggerBuilder::with_record_hook()].
+pub type RecordHook = Box<dyn Fn(&log::Metadata, &std::fmt::Arguments) + Send + Sync>;
+
 thread_local! {
-    static IS_REENTRANT_LOGGING_CALL: Cell<bool> = Cell::new(false);
+    static IS_REENTRANT_LOGGING_CALL: Cell<bool> = const { Cell::new(false) };
+
+    /// Set while the record hook is running on this thread. Records logged from within the hook
+    /// are written as usual, but the hook is not called for them again.
+    static IS_IN_RECORD_HOOK: Cell<bool> = const { Cell::new(false) };
+
+    /// A scratch buffer used when a message needs to be formatted before it can be written, for
+    /// instance to indent multi-line messages. Reused between log calls to avoid allocations.
+    static MESSAGE_BUFFER: RefCell<String> = const { RefCell::new(String::new()) };
+
+    /// An output target that replaces the logger's main output target for records written from
+    /// this thread. See [`crate::set_thread_target()`].
+    static THREAD_TARGET: RefCell<Option<OutputTargetImpl>> = const { RefCell::new(None) };
+}
+
+/// A logger that forwards everything to NIH-log's global logger, if it has been installed. Used by
+/// [`LoggerBuilder::build_global_if_none()`][crate::LoggerBuilder::build_global_if_none()] and
+/// [`init_early()`][crate::init_early()] to register with the `log` crate before the global logger
+/// exists. In the latter case records are buffered until the global logger is installed.
+pub struct GlobalLoggerProxy;
+
+impl Log for GlobalLoggerProxy {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        if let Some(enabled) = answer_version_probe(metadata) {
+            return enabled;
+        }
+
+        match crate::LOGGER_INSTANCE.get() {
+            Some(logger) => logger.enabled(metadata),
+            None => crate::early::is_installed() && metadata.level() <= log::max_level(),
+        }
+    }
+
+    fn log(&self, record: &log::Record) {
+        match crate::LOGGER_INSTANCE.get() {
+            Some(logger) => logger.log(record),
+            None => crate::early::capture(record),
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(logger) = crate::LOGGER_INSTANCE.get() {
+            logger.flush();
+        }
+    }
+}
+
+/// The target NIH-log's loggers always report as enabled. Together with
+/// [`NEGATIVE_VERSION_PROBE_TARGET`], which they always report as disabled, this lets another
+/// version of NIH-log linked into the same binary recognize that the logger registered with the
+/// `log` crate is an NIH-log logger. Loggers that only filter on the log level give the same answer
+/// for both targets. These names need to stay the same between versions.
+pub const VERSION_PROBE_TARGET: &str = "nih_log::__version_probe";
+/// See [`VERSION_PROBE_TARGET`].
+pub const NEGATIVE_VERSION_PROBE_TARGET: &str = "nih_log::__negative_version_probe";
+
+/// Let other versions of NIH-log recognize this logger, see [`VERSION_PROBE_TARGET`]. Returns
+/// `None` for regular metadata.
+fn answer_version_probe(metadata: &log::Metadata) -> Option<bool> {
+    match metadata.target() {
+        VERSION_PROBE_TARGET => Some(true),
+        NEGATIVE_VERSION_PROBE_TARGET => Some(false),
+        _ => None,
+    }
+}
+
+/// Whether the logger registered with the `log` crate is an NIH-log logger. When this is called
+/// after NIH-log failed to register itself, the logger belongs to another version of NIH-log.
+pub fn log_facade_is_nih_log() -> bool {
+    let probe = |target| {
+        log::logger().enabled(
+            &log::Metadata::builder()
+                .level(Level::Error)
+                .target(target)
+                .build(),
+        )
+    };
+
+    probe(VERSION_PROBE_TARGET) && !probe(NEGATIVE_VERSION_PROBE_TARGET)
+}
+
+/// Which records are written to a level route's output target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RouteLevels {
+    /// Records at or above this level.
+    AtOrAbove(LevelFilter),
+    /// Only records with exactly this level.
+    Exactly(Level),
+}
+
+impl RouteLevels {
+    /// The most verbose level that's written to the route's target.
+    pub fn max_level(self) -> LevelFilter {
+        match self {
+            RouteLevels::AtOrAbove(filter) => filter,
+            RouteLevels::Exactly(route_level) => route_level.to_level_filter(),
+        }
+    }
+
+    /// Whether a record with this level should be written to the route's target.
+    pub fn matches(self, level: Level) -> bool {
+        match self {
+            RouteLevels::AtOrAbove(filter) => level <= filter,
+            RouteLevels::Exactly(route_level) => level == route_level,
+        }
+    }
+}
+
+/// A level tag with its ANSI color escape sequences. See [`ansi_level_tags()`].
+pub struct AnsiLevelTag {
+    /// The escape sequences and the tag itself.
+    bytes: Vec<u8>,
+    /// The number of characters in `bytes` that are not part of an escape sequence.
+    visible_chars: usize,
+}
+
+/// Precompute the colored level tags for a theme, in the same order as the [`Level`] enum. These
+/// are used instead of [`WriteExt::set_fg_color()`] when the output target writes ANSI escape
+/// sequences.
+pub fn ansi_level_tags(theme: &Theme, level_labels: &[String; 5]) -> [AnsiLevelTag; 5] {
+    [
+        Level::Error,
+        Level::Warn,
+        Level::Info,
+        Level::Debug,
+        Level::Trace,
+    ]
+    .map(|level| {
+        let tag = &level_labels[level as usize - 1];
+        let mut writer = Ansi::new(Vec::new());
+        if let Some(color) = theme.level_color(level) {
+            let _ = writer.set_color(ColorSpec::new().set_fg(Some(color)));
+            let _ = writer.write_all(tag.as_bytes());
+            let _ = writer.reset();
+        } else {
+            let _ = writer.write_all(tag.as_bytes());
+        }
+
+        AnsiLevelTag {
+            bytes: writer.into_inner(),
+            visible_chars: tag.chars().count(),
+        }
+    })
+}
+
+/// Information about a record that's determined once in `log()`, so it's the same for every output
+/// target the record is written to.
+#[derive(Debug)]
+struct RecordInfo {
+    /// The time the record was logged at, in the logger's local time offset.
+    time: time::OffsetDateTime,
+    /// The record's sequence number, if sequence numbers are enabled.
+    sequence_number: Option<u64>,
+    /// The time since the previous record, if this should be shown.
+    interline_delta: Option<Duration>,
+    /// A formatted backtrace written after the message, if one was captured for this record.
+    backtrace: Option<String>,
+    /// Formatted records from the error context ring that are written before this record.
+    error_context: Vec<Vec<u8>>,
+    /// The key-value pairs attached to this record through [`kv_log!()`][crate::kv_log!()].
+    fields: Vec<(&'static str, String)>,
+    /// The diff attached to this record through [`diff!()`][crate::diff!()].
+    diff: Vec<(DiffLine, String)>,
 }
 
 /// The NIH-log logger. Construct one using the [`LoggerBuilder`].
 pub struct Logger {
-    /// The maximum log level filter. This is already set globally using [`log::set_max_level()`]
-    /// but it's probably a good idea to check it again regardless.
+    /// The maximum log level filter for the regular output target. The most verbose level out of
+    /// this and `route_max_level` is already set globally using [`log::set_max_level()`] but it's
+    /// probably a good idea to check it again regardless.
     pub max_log_level: LevelFilter,
+    /// The most verbose level any of the level routes accepts. Routes can be more verbose than the
+    /// regular output target.
+    pub route_max_level: LevelFilter,
     /// If set to `true`, then the module path is always shown. Useful for debug builds and to
     /// configure the module blacklist.
     pub always_show_module_path: bool,
+    /// If set to `true`, then source locations are written as clickable OSC 8 hyperlinks when the
+    /// output target supports colors.
+    pub clickable_locations: bool,
+    /// How the current thread is shown for debug and trace messages.
+    pub thread_format: ThreadFormat,
+    /// If set to `true`, then the operating system's thread ID is shown instead of Rust's thread
+    /// ID, if it's available on this platform.
+    pub os_thread_id: bool,
+    /// If set to `true`, then the name of the main thread is shown like any other thread name.
+    pub show_main_thread_name: bool,
+    /// If set to `true`, then no thread information is shown for threads without a name.
+    pub hide_unnamed_threads: bool,
+    /// If set to `true`, then only the log message itself is written, without a timestamp, log
+    /// level, or any other context.
+    pub message_only: bool,
+    /// If set to `true`, then info records are written like in `message_only` mode.
+    pub plain_info: bool,
+    /// If set to `true`, then records are written as `key=value` pairs in the logfmt format.
+    pub logfmt: bool,
+    /// A fixed string written at the start of every line, before the timestamp.
+    pub line_prefix: Option<String>,
+    /// The separator written between the columns of a log line.
+    pub column_separator: String,
+    /// If set to `true`, then the continuation lines of multi-line messages are indented to line
+    /// up with the message's first line.
+    pub multiline_indent: bool,
+    /// If set, then messages are hard-wrapped so lines don't exceed this many columns. Wrapped
+    /// lines are indented like with `multiline_indent`.
+    pub wrap_width: Option<usize>,
+    /// If set to `true`, then a single trailing newline is removed from messages so it isn't
+    /// doubled.
+    pub normalize_newlines: bool,
+    /// The capacity reserved for the thread-local message scratch buffer.
+    pub line_capacity: usize,
+    /// If set to `true`, then every record is prefixed with a monotonically increasing sequence
+    /// number.
+    pub sequence_numbers: bool,
+    /// The sequence number for the next record. Only used when `sequence_numbers` is set.
+    pub next_sequence_number: AtomicU64,
+    /// A custom format for the timestamp at the start of every line. [`TIME_FORMAT_DESCRIPTION`]
+    /// is used if this is not set.
+    pub time_format: Option<OwnedFormatItem>,
+    /// If set to `true`, then the timestamp at the start of every line is replaced by the system's
+    /// monotonic clock. See [`monotonic_time()`].
+    pub monotonic_timestamps: bool,
+    /// If set to `false`, then no timestamp is written at the start of every line.
+    pub timestamps: bool,
+    /// If set to `true`, then the time since the previous record is shown after the timestamp.
+    pub interline_delta: bool,
+    /// Records at or above this level are followed by a backtrace.
+    pub backtrace_level: LevelFilter,
+    /// Recently filtered out records that are written before the next error record. See
+    /// [`LoggerBuilder::with_error_context_ring()`][crate::LoggerBuilder::with_error_context_ring()].
+    pub error_context_ring: Option<ErrorContextRing>,
+    /// The time the logger was created. `last_record_nanos` is relative to this.
+    pub start_time: Instant,
+    /// The time the previous record was logged at in nanoseconds since `start_time`, or
+    /// `u64::MAX` if nothing has been logged yet.
+    pub last_record_nanos: AtomicU64,
     /// The local time offset. Queried once at startup to avoid having to do this over and over
     /// again.
     pub local_time_offset: UtcOffset,
     /// The output target for the logger.
     pub output_target: Mutex<OutputTargetImpl>,
+    /// Additional output targets that records matching the paired levels are also written to.
+    pub level_routes: Vec<(RouteLevels, Mutex<OutputTargetImpl>)>,
+    /// Overrides the output target's default color behavior. Also used for the temporary output
+    /// targets created for reentrant logging calls.
+    pub color_mode: ColorMode,
+    /// If set to `true`, then writes to STDERR and files are not buffered. Also applied to targets
+    /// created after the logger was built.
+    pub unbuffered: bool,
+    /// The size of the write buffer for files and raw STDERR streams, if it should be changed.
+    /// Also applied to targets created after the logger was built.
+    pub buffer_capacity: Option<usize>,
+    /// The thread that periodically flushes the logger, if a flush interval was set.
+    pub flush_thread: FlushThread,
+    /// If set, then targets are only flushed after a record once more than this many bytes have
+    /// been written to them since the last flush. Error records are always flushed.
+    pub flush_threshold: Option<u64>,
+    /// The colors used when the output target supports colors.
+    pub theme: Theme,
+    /// The colored level tags for targets that write ANSI escape sequences, indexed by
+    /// `level as usize - 1`. Computed from `theme` when the logger is built.
+    pub ansi_level_tags: [AnsiLevelTag; 5],
+    /// The tags written for each log level, indexed by `level as usize - 1`.
+    pub level_labels: [String; 5],
+    /// The level tags are padded with spaces to this width in characters. Computed from the
+    /// longest tag when the logger is built if the tags should be aligned, and zero otherwise.
+    pub level_tag_width: usize,
     /// Names of crates module paths that should be excluded from the log. Case sensitive, and only
     /// matches whole crate names and paths. Both the crate name and module path are checked
     /// separately to allow for a little bit of flexibility.
     pub module_blacklist: HashSet<String>,
+    /// Names of crates and module paths whose module paths should not be shown. Uses the same
+    /// matching rules as `module_blacklist`.
+    pub hidden_module_paths: HashSet<String>,
+    /// Names of crates and modules whose messages are prefixed with a marker and written in bold.
+    pub highlighted_crates: HashSet<String>,
+    /// If set to `true`, then the record's target is shown alongside the module path when it
+    /// differs from the module path.
+    pub show_target: bool,
+    /// An optional user provided filter function. Records for which this function returns `false`
+    /// are not logged.
+    pub filter_fn: Option<FilterFn>,
+    /// An optional user provided function that's called for every record that passes the filters,
+    /// before the record is formatted.
+    pub record_hook: Option<RecordHook>,
+    /// If set, then a session header with these additional `key=value` pairs is written when
+    /// logging to a file.
+    pub session_header: Option<Vec<(String, String)>>,
+    /// If set to `true`, then a session footer is written to file targets on
+    /// [`crate::shutdown()`].
+    pub session_footer: bool,
+    /// Whether the session footer has already been written. The footer is only written once, even
+    /// if [`crate::shutdown()`] is called multiple times.
+    pub session_footer_written: AtomicBool,
+    /// If set to `true`, then a UTF-8 byte order mark is written to newly created log files.
+    pub utf8_bom: bool,
+    /// The total number of bytes successfully written by the logger.
+    pub bytes_written: AtomicU64,
+    /// If set to `true`, then all records are dropped. See [`crate::pause()`].
+    pub paused: AtomicBool,
+    /// If set to `true`, then the maximum log level is capped at [`LevelFilter::Warn`]. See
+    /// [`crate::set_quiet()`].
+    pub quiet: AtomicBool,
+    /// If set to `true`, then a line is written when the effective log level changes at runtime.
+    /// See [`log_level_change()`][Self::log_level_change()].
+    pub level_change_markers: bool,
+    /// Whether the log level can be changed through signals. See
+    /// [`apply_signal_adjustments()`][Self::apply_signal_adjustments()].
+    pub signal_control: bool,
+    /// The number of levels the maximum log level has been made more verbose by through signals.
+    /// Negative values make it less verbose.
+    pub level_adjustment: AtomicIsize,
+    /// Whether this logger was registered with the `log` crate. If it wasn't, then changing the
+    /// effective log level should not touch [`log::set_max_level()`].
+    pub is_log_facade: AtomicBool,
+    /// Another logger that every record is also forwarded to.
+    pub chained_logger: Option<Box<dyn Log>>,
+    /// Whether reentrant logging calls should be detected. See the readme and the comments in
+    /// `log()` for more information.
+    pub reentrancy_guard: bool,
+    /// If set to `true`, then the parent directories of log files are created if they don't exist.
+    pub create_log_dirs: bool,
+    /// If set to `true`, then [`crate::prepare_for_fork()`] also closes log files.
+    pub close_files_before_fork: bool,
+    /// The parsed `NIH_LOG` configuration the logger was built with, including any problems that
+    /// occurred while opening the output target.
+    pub env_config: EnvConfig,
+    /// If set, then a marker is written to this target the first time a reentrant logging call
+    /// is detected.
+    pub reentrancy_report: Option<OutputTarget>,
+    /// Whether a reentrant logging call has already been reported to `reentrancy_report`.
+    pub reentrancy_reported: AtomicBool,
+}
+
+/// A background thread that flushes the logger at a fixed interval. See
+/// [`LoggerBuilder::with_flush_interval()`][crate::LoggerBuilder::with_flush_interval()].
+#[derive(Default)]
+pub struct FlushThread {
+    handle: Mutex<Option<JoinHandle<()>>>,
+    /// Set to `true` to make the thread exit. The condition variable is used to wake the thread up
+    /// early.
+    stopped: Mutex<bool>,
+    condvar: Condvar,
+}
+
+impl FlushThread {
+    /// Start flushing `logger` every `interval`. If the thread could not be spawned, then a warning
+    /// is printed to STDERR and the logger is only flushed after every record as usual.
+    pub fn spawn(&self, logger: &'static Logger, interval: Duration) {
+        let result = std::thread::Builder::new()
+            .name(String::from("nih-log flush"))
+            .spawn(move || {
+                let flush_thread = &logger.flush_thread;
+                let mut stopped = flush_thread.stopped.lock().expect("Mutex poisoned");
+                while !*stopped {
+                    let (guard, timeout) = flush_thread
+                        .condvar
+                        .wait_timeout(stopped, interval)
+                        .expect("Mutex poisoned");
+                    stopped = guard;
+                    if timeout.timed_out() && !*stopped {
+                        // The lock is released while flushing so stopping the thread doesn't need
+                        // to wait for the flush
+                        drop(stopped);
+                        logger.flush();
+                        stopped = flush_thread.stopped.lock().expect("Mutex poisoned");
+                    }
+                }
+            });
+
+        match result {
+            Ok(handle) => *self.handle.lock().expect("Mutex poisoned") = Some(handle),
+            Err(err) => eprintln!("Could not spawn NIH-log's flush thread: {err}"),
+        }
+    }
+
+    /// Stop the thread and wait for it to exit. Does nothing if the thread isn't running.
+    pub fn stop(&self) {
+        let Some(handle) = self.handle.lock().expect("Mutex poisoned").take() else {
+            return;
+        };
+
+        *self.stopped.lock().expect("Mutex poisoned") = true;
+        self.condvar.notify_all();
+        let _ = handle.join();
+    }
+}
+
+/// The last few records that were filtered out because of their log level, already formatted.
+/// These are written before the next error record. See
+/// [`LoggerBuilder::with_error_context_ring()`][crate::LoggerBuilder::with_error_context_ring()].
+pub struct ErrorContextRing {
+    lines: Mutex<VecDeque<Vec<u8>>>,
+    capacity: usize,
+}
+
+impl ErrorContextRing {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            lines: Mutex::new(VecDeque::with_capacity(capacity)),
+            capacity,
+        }
+    }
+
+    /// Add a formatted record, dropping the oldest record if the ring is full.
+    pub fn push(&self, line: Vec<u8>) {
+        let mut lines = match self.lines.lock() {
+            Ok(lines) => lines,
+            Err(err) => err.into_inner(),
+        };
+        if lines.len() == self.capacity {
+            lines.pop_front();
+        }
+        lines.push_back(line);
+    }
+
+    /// Remove and return all buffered records, from oldest to newest.
+    pub fn take(&self) -> Vec<Vec<u8>> {
+        let mut lines = match self.lines.lock() {
+            Ok(lines) => lines,
+            Err(err) => err.into_inner(),
+        };
+
+        lines.drain(..).collect()
+    }
 }
 
 impl Logger {
+    /// Capture and format a backtrace if backtraces are enabled for this level. This respects the
+    /// `RUST_BACKTRACE` and `RUST_LIB_BACKTRACE` environment variables.
+    fn capture_backtrace(&self, level: Level) -> Option<String> {
+        if level > self.backtrace_level {
+            return None;
+        }
+
+        let backtrace = Backtrace::capture();
+        match backtrace.status() {
+            BacktraceStatus::Captured => Some(backtrace.to_string().trim_end().to_owned()),
+            _ => None,
+        }
+    }
+
+    /// The current time in the logger's local time offset.
+    fn current_time(&self) -> time::OffsetDateTime {
+        time::OffsetDateTime::now_utc().to_offset(self.local_time_offset)
+    }
+
+    /// Gather the per-record information for a record that's about to be written. This advances
+    /// the sequence number and the interline delta, so it should be called once per record.
+    fn record_info(
+        &self,
+        level: Level,
+        fields: Vec<(&'static str, String)>,
+        diff: Vec<(DiffLine, String)>,
+    ) -> RecordInfo {
+        RecordInfo {
+            time: self.current_time(),
+            sequence_number: self
+                .sequence_numbers
+                .then(|| self.next_sequence_number.fetch_add(1, Ordering::Relaxed)),
+            interline_delta: self.interline_delta.then(|| self.interline_delta()),
+            backtrace: self.capture_backtrace(level),
+            error_context: match &self.error_context_ring {
+                Some(ring) if level <= Level::Error => ring.take(),
+                _ => Vec::new(),
+            },
+            fields,
+            diff,
+        }
+    }
+
+    /// Format a record that was filtered out because of its log level and add it to the error
+    /// context ring, if that is enabled. Like the preview, these are formatted without colors and
+    /// without advancing the sequence number.
+    fn push_error_context(
+        &self,
+        record: &log::Record,
+        fields: Vec<(&'static str, String)>,
+        diff: Vec<(DiffLine, String)>,
+    ) {
+        if let Some(ring) = &self.error_context_ring {
+            let info = RecordInfo {
+                time: self.current_time(),
+                sequence_number: None,
+                interline_delta: None,
+                backtrace: None,
+                error_context: Vec::new(),
+                fields,
+                diff,
+            };
+
+            let mut buffer = Vec::new();
+            self.format_record(&mut CountingWriter::new(&mut buffer), record, &info);
+            ring.push(buffer);
+        }
+    }
+
+    /// The time passed since the previous record, and update the previous record's time to now.
+    /// Returns zero for the first record.
+    fn interline_delta(&self) -> Duration {
+        let now = self.start_time.elapsed().as_nanos() as u64;
+        match self.last_record_nanos.swap(now, Ordering::Relaxed) {
+            u64::MAX => Duration::ZERO,
+            previous => Duration::from_nanos(now.saturating_sub(previous)),
+        }
+    }
+
+    /// Write an info record noting that the effective log level changed from `from` to `to`, if
+    /// level change markers are enabled and the level actually changed. The record is written
+    /// even if info records are currently filtered out.
+    pub fn log_level_change(&self, from: LevelFilter, to: LevelFilter) {
+        if !self.level_change_markers || from == to || self.paused.load(Ordering::Relaxed) {
+            return;
+        }
+
+        let info = self.record_info(Level::Info, Vec::new(), Vec::new());
+        self.write_record_to_targets(
+            &log::Record::builder()
+                .args(format_args!("log level changed from {from:?} to {to:?}"))
+                .level(Level::Info)
+                .target(module_path!())
+                .module_path_static(Some(module_path!()))
+                .build(),
+            &info,
+            false,
+        );
+    }
+
+    /// The maximum log level across the regular output target and all level routes, taking quiet
+    /// mode and signal adjustments into account.
+    pub fn effective_max_level(&self) -> LevelFilter {
+        let route_max_level = if self.quiet.load(Ordering::Relaxed) {
+            self.route_max_level.min(LevelFilter::Warn)
+        } else {
+            self.route_max_level
+        };
+
+        self.target_max_level().max(route_max_level)
+    }
+
+    /// The maximum log level for the regular output target, taking quiet mode and signal
+    /// adjustments into account.
+    pub fn target_max_level(&self) -> LevelFilter {
+        let adjusted_level =
+            (self.max_log_level as isize + self.level_adjustment.load(Ordering::Relaxed)) as usize;
+        let max_log_level = LevelFilter::iter()
+            .nth(adjusted_level)
+            .unwrap_or(self.max_log_level);
+
+        if self.quiet.load(Ordering::Relaxed) {
+            max_log_level.min(LevelFilter::Warn)
+        } else {
+            max_log_level
+        }
+    }
+
+    /// The maximum level that should be set with [`log::set_max_level()`]. If the level can be
+    /// changed through signals, then the `log` crate cannot be notified of those changes, and the
+    /// error context ring also needs to see filtered out records. In those cases all records are
+    /// let through and the logger filters them itself.
+    pub fn facade_max_level(&self) -> LevelFilter {
+        if self.signal_control || self.error_context_ring.is_some() {
+            log::STATIC_MAX_LEVEL
+        } else {
+            self.effective_max_level()
+        }
+    }
+
+    /// Apply the log level changes requested through signals since this was last called, and
+    /// write a level change marker if the level changed.
+    pub fn apply_signal_adjustments(&self) {
+        #[cfg(unix)]
+        if self.signal_control {
+            let pending_adjustment = crate::signal::take_pending_adjustment();
+            if pending_adjustment == 0 {
+                return;
+            }
+
+            let previous_level = self.effective_max_level();
+            let adjustment = crate::signal::clamp_adjustment(
+                self.max_log_level,
+                log::STATIC_MAX_LEVEL,
+                self.level_adjustment.load(Ordering::Relaxed),
+                pending_adjustment,
+            );
+            self.level_adjustment.store(adjustment, Ordering::Relaxed);
+
+            self.log_level_change(previous_level, self.effective_max_level());
+        }
+    }
+
+    /// Gather the logger's current configuration. See [`crate::config_snapshot()`].
+    pub fn config_snapshot(&self) -> ConfigSnapshot {
+        let describe_target = |target: &Mutex<OutputTargetImpl>| match target.lock() {
+            Ok(target) => format!("{target:?}"),
+            Err(err) => format!("{:?}", err.into_inner()),
+        };
+
+        let mut module_blacklist: Vec<String> = self.module_blacklist.iter().cloned().collect();
+        module_blacklist.sort();
+
+        ConfigSnapshot {
+            max_level: self.effective_max_level(),
+            target_max_level: self.target_max_level(),
+            quiet: self.quiet.load(Ordering::Relaxed),
+            paused: self.paused.load(Ordering::Relaxed),
+            module_blacklist,
+            always_show_module_path: self.always_show_module_path,
+            local_time_offset: self.local_time_offset,
+            target: describe_target(&self.output_target),
+            level_routes: self
+                .level_routes
+                .iter()
+                .map(|(levels, target)| format!("{levels:?}: {}", describe_target(target)))
+                .collect(),
+        }
+    }
+
     /// Check if a target is enabled by comparing it to `self.module_blacklist`. If it contains a
     /// colon, also check if the first part (assumed to be a crate name) matches the blacklist.
     pub fn target_enabled(&self, target: &str) -> bool {
@@ -53,103 +682,777 @@ impl Logger {
         !self.module_blacklist.contains(target)
     }
 
-    fn do_log(&self, mut writer: &mut dyn WriteExt, record: &log::Record) {
+    /// Check whether a module path should be written, using the same matching rules as
+    /// [`target_enabled()`][Self::target_enabled()] but for `self.hidden_module_paths`.
+    pub fn module_path_shown(&self, module_path: &str) -> bool {
+        if let Some((crate_name, _)) = module_path.split_once(':') {
+            if self.hidden_module_paths.contains(crate_name) {
+                return false;
+            }
+        }
+
+        !self.hidden_module_paths.contains(module_path)
+    }
+
+    /// Check whether a module path should be highlighted, using the same matching rules as
+    /// [`target_enabled()`][Self::target_enabled()] but for `self.highlighted_crates`.
+    pub fn module_path_highlighted(&self, module_path: &str) -> bool {
+        if self.highlighted_crates.is_empty() {
+            return false;
+        }
+
+        if let Some((crate_name, _)) = module_path.split_once(':') {
+            if self.highlighted_crates.contains(crate_name) {
+                return true;
+            }
+        }
+
+        self.highlighted_crates.contains(module_path)
+    }
+
+    /// Check whether a record's metadata passes the user provided filter function, if one was set.
+    pub fn filter_fn_enabled(&self, metadata: &log::Metadata) -> bool {
+        match &self.filter_fn {
+            Some(filter_fn) => filter_fn(metadata),
+            None => true,
+        }
+    }
+
+    /// Log the problems recorded in the `NIH_LOG` configuration while the logger was being built,
+    /// like a log file that could not be opened. These could not be logged when they occurred
+    /// since the logger did not exist yet. Called once after the logger has been installed. These
+    /// used to be printed to STDERR unconditionally, so they're written to the output targets
+    /// directly without going through the log level, module, and filter function checks.
+    pub fn log_env_warnings(&self) {
+        for warning in &self.env_config.warnings {
+            let info = self.record_info(Level::Warn, Vec::new(), Vec::new());
+            self.write_record_to_targets(
+                &log::Record::builder()
+                    .args(format_args!("{warning}"))
+                    .level(Level::Warn)
+                    .target(module_path!())
+                    .module_path_static(Some(module_path!()))
+                    .build(),
+                &info,
+                false,
+            );
+        }
+    }
+
+    /// Write the UTF-8 byte order mark and the session header to the output target if those were
+    /// configured and the logger writes to a file. Called once after the logger has been
+    /// installed.
+    pub fn write_session_header(&self) {
+        for target in
+            std::iter::once(&self.output_target).chain(self.level_routes.iter().map(|(_, t)| t))
+        {
+            let mut target = match target.lock() {
+                Ok(target) => target,
+                Err(err) => err.into_inner(),
+            };
+            self.write_session_header_to(&mut target);
+        }
+    }
+
+    /// The contents of the first ring buffer target, checking the main output target before the
+    /// level routes. See [`crate::ring_buffer_contents()`].
+    pub fn ring_buffer_contents(&self) -> Option<Vec<u8>> {
+        std::iter::once(&self.output_target)
+            .chain(self.level_routes.iter().map(|(_, t)| t))
+            .find_map(|target| {
+                let target = match target.lock() {
+                    Ok(target) => target,
+                    Err(err) => err.into_inner(),
+                };
+
+                target.ring_buffer_contents()
+            })
+    }
+
+    /// Flush and reopen all file targets, and write the session header to the new files if that's
+    /// enabled. See [`crate::rotate_file()`]. All files are reopened even if one of them fails, in
+    /// which case the first error is returned.
+    pub fn rotate_files(&self) -> std::io::Result<()> {
+        let mut result = Ok(());
+        for target in
+            std::iter::once(&self.output_target).chain(self.level_routes.iter().map(|(_, t)| t))
+        {
+            let mut target = match target.lock() {
+                Ok(target) => target,
+                Err(err) => err.into_inner(),
+            };
+            match target.reopen_file() {
+                Ok(()) => self.write_session_header_to(&mut target),
+                Err(err) => {
+                    if result.is_ok() {
+                        result = Err(err);
+                    }
+                }
+            }
+        }
+
+        result
+    }
+
+    /// Apply the logger's color and buffering settings to an output target that's created after
+    /// the logger was built. The session header is also written if the target writes to a file.
+    pub fn configure_target(&self, target: OutputTargetImpl) -> OutputTargetImpl {
+        let mut target = target.with_color_mode(self.color_mode);
+        if let Some(capacity) = self.buffer_capacity {
+            target = target.with_buffer_capacity(capacity);
+        }
+        let mut target = if self.unbuffered {
+            target.unbuffered()
+        } else {
+            target
+        };
+        self.write_session_header_to(&mut target);
+
+        target
+    }
+
+    /// Write the session header and byte order mark for a single output target. See
+    /// [`write_session_header()`][Self::write_session_header()].
+    fn write_session_header_to(&self, target: &mut OutputTargetImpl) {
+        if !target.is_file() {
+            return;
+        }
+
+        if self.utf8_bom {
+            target.write_utf8_bom();
+        }
+
+        let Some(header_fields) = &self.session_header else {
+            return;
+        };
+
+        let mut writer = target.writer();
+        let current_time = self.current_time();
+        let _ = write!(writer, "=== log started at ");
+        let _ = current_time.format_into(&mut writer, SESSION_TIME_FORMAT_DESCRIPTION);
+        for (key, value) in header_fields {
+            let _ = write!(writer, " {key}={value}");
+        }
+        let _ = writeln!(writer, " ===");
+        let _ = writer.flush();
+    }
+
+    /// Write the session footer to all file targets if that was configured. Called from
+    /// [`crate::shutdown()`]. Only the first call writes anything.
+    pub fn write_session_footer(&self) {
+        if !self.session_footer || self.session_footer_written.swap(true, Ordering::Relaxed) {
+            return;
+        }
+
+        for target in
+            std::iter::once(&self.output_target).chain(self.level_routes.iter().map(|(_, t)| t))
+        {
+            let mut target = match target.lock() {
+                Ok(target) => target,
+                Err(err) => err.into_inner(),
+            };
+            if !target.is_file() {
+                continue;
+            }
+
+            let mut writer = target.writer();
+            let current_time = self.current_time();
+            let _ = write!(writer, "=== log ended at ");
+            let _ = current_time.format_into(&mut writer, SESSION_TIME_FORMAT_DESCRIPTION);
+            let _ = writeln!(writer, " ===");
+            let _ = writer.flush();
+        }
+    }
+
+    /// Finish writing gzip compressed files and publish atomic files for all targets that write
+    /// those. Called from [`crate::shutdown()`] after flushing the logger.
+    pub fn finish_files(&self) {
+        for target in
+            std::iter::once(&self.output_target).chain(self.level_routes.iter().map(|(_, t)| t))
+        {
+            let mut target = match target.lock() {
+                Ok(target) => target,
+                Err(err) => err.into_inner(),
+            };
+            let _ = target.finish_files();
+        }
+    }
+
+    /// Flush all output targets, and close their files if `close_files_before_fork` is set. See
+    /// [`crate::prepare_for_fork()`].
+    pub fn prepare_for_fork(&self) {
+        self.flush();
+        if !self.close_files_before_fork {
+            return;
+        }
+
+        for target in
+            std::iter::once(&self.output_target).chain(self.level_routes.iter().map(|(_, t)| t))
+        {
+            let mut target = match target.lock() {
+                Ok(target) => target,
+                Err(err) => err.into_inner(),
+            };
+            let _ = target.close_file();
+        }
+        let _ = THREAD_TARGET.try_with(|thread_target| {
+            if let Ok(Some(target)) = thread_target.try_borrow_mut().as_deref_mut() {
+                let _ = target.close_file();
+            }
+        });
+    }
+
+    /// Call the record hook if one was set. This happens before any of the output targets are
+    /// locked, so the hook can safely log. To avoid infinite recursion, the hook is not called
+    /// again for records logged from within the hook.
+    fn call_record_hook(&self, record: &log::Record) {
+        let Some(record_hook) = &self.record_hook else {
+            return;
+        };
+
+        let _ = IS_IN_RECORD_HOOK.try_with(|is_in_record_hook| {
+            if !is_in_record_hook.replace(true) {
+                record_hook(record.metadata(), record.args());
+                is_in_record_hook.set(false);
+            }
+        });
+    }
+
+    /// Write a marker to `self.reentrancy_report` if this is the first reentrant logging call.
+    fn report_reentrant_call(&self, record: &log::Record) {
+        let Some(report_target) = &self.reentrancy_report else {
+            return;
+        };
+        if self.reentrancy_reported.swap(true, Ordering::Relaxed) {
+            return;
+        }
+
+        let Ok(mut target) = open_output_target(report_target.clone(), self.create_log_dirs) else {
+            return;
+        };
+        let writer = target.writer();
+        let current_time = self.current_time();
+        let _ = write!(writer, "=== ");
+        let _ = current_time.format_into(&mut *writer, SESSION_TIME_FORMAT_DESCRIPTION);
+        let _ = writeln!(
+            writer,
+            " reentrant logging call detected in {}, this usually means that logging allocated \
+             memory where allocations are not allowed. Further occurrences are not reported. ===",
+            record.module_path().unwrap_or_else(|| record.target())
+        );
+        let _ = writer.flush();
+    }
+
+    /// Write a record's message to `writer`. If `self.normalize_newlines` is set, then the message
+    /// is formatted into the scratch buffer first so a single trailing newline can be removed.
+    fn write_message_body(&self, writer: &mut CountingWriter, args: &std::fmt::Arguments) {
+        if self.normalize_newlines {
+            self.with_formatted_message(args, |message| {
+                let _ = write!(writer, "{}", message.strip_suffix('\n').unwrap_or(message));
+            });
+        } else {
+            write_message(writer, args);
+        }
+    }
+
+    /// Format a message into the thread-local scratch buffer and pass the result to `f`. The
+    /// buffer's capacity is reserved up front according to `self.line_capacity`.
+    fn with_formatted_message(&self, args: &std::fmt::Arguments, f: impl FnOnce(&str)) {
+        MESSAGE_BUFFER.with(|buffer| match buffer.try_borrow_mut() {
+            Ok(mut buffer) => {
+                buffer.clear();
+                buffer.reserve(self.line_capacity);
+                format_message(&mut buffer, args);
+
+                f(&buffer)
+            }
+            // The buffer can only already be borrowed when the write in `f` results in a
+            // reentrant logging call
+            Err(_) => {
+                let mut message = String::new();
+                format_message(&mut message, args);
+
+                f(&message)
+            }
+        })
+    }
+
+    /// Format a record and write it to `target`, while keeping track of the number of bytes
+    /// written. The target is flushed after every record, unless a flush threshold is set.
+    fn write_record(&self, target: &mut OutputTargetImpl, record: &log::Record, info: &RecordInfo) {
+        let mut writer = CountingWriter::new(target.writer());
+        for line in &info.error_context {
+            let _ = writer.write_all(line);
+        }
+        self.format_record(&mut writer, record, info);
+        let bytes_written = writer.bytes_written;
+
+        // Every line should be flushed immediately to avoid surprises, unless the user explicitly
+        // asked for fewer flushes. Errors are always flushed since they may precede a crash.
+        target.flush_after_record(
+            bytes_written,
+            self.flush_threshold,
+            record.level() <= Level::Error,
+        );
+
+        self.bytes_written
+            .fetch_add(bytes_written, Ordering::Relaxed);
+    }
+
+    /// Format a record the same way it would be written to a target without color support, without
+    /// writing it anywhere. This doesn't advance the sequence number or the interline delta, and
+    /// no backtrace is captured. See [`crate::format_preview()`].
+    pub fn format_preview(&self, record: &log::Record) -> String {
+        let info = RecordInfo {
+            time: self.current_time(),
+            sequence_number: self
+                .sequence_numbers
+                .then(|| self.next_sequence_number.load(Ordering::Relaxed)),
+            interline_delta: self.interline_delta.then_some(Duration::ZERO),
+            backtrace: None,
+            error_context: Vec::new(),
+            fields: Vec::new(),
+            diff: Vec::new(),
+        };
+
+        let mut buffer = Vec::new();
+        self.format_record(&mut CountingWriter::new(&mut buffer), record, &info);
+
+        String::from_utf8_lossy(&buffer).into_owned()
+    }
+
+    /// Write a record to the main output target and to all level routes that apply to it.
+    fn write_record_to_targets(
+        &self,
+        record: &log::Record,
+        info: &RecordInfo,
+        filter_levels: bool,
+    ) {
+        // Level routes may be more verbose than the regular output target. A thread's own target
+        // replaces the regular output target, so it also uses that target's level.
+        if filter_levels && record.level() > self.target_max_level() {
+            self.write_record_to_routes(record, info);
+            return;
+        }
+
+        // The thread's own target is not available during thread-local destruction, or when
+        // reentrant logging calls are not detected
+        let wrote_to_thread_target = THREAD_TARGET
+            .try_with(|thread_target| match thread_target.try_borrow_mut() {
+                Ok(mut thread_target) => match thread_target.as_mut() {
+                    Some(target) => {
+                        target.set_record_level(record.level());
+                        self.write_record(target, record, info);
+                        true
+                    }
+                    None => false,
+                },
+                Err(_) => false,
+            })
+            .unwrap_or(false);
+
+        if !wrote_to_thread_target {
+            let mut target = match self.output_target.lock() {
+                Ok(target) => target,
+                Err(err) => err.into_inner(),
+            };
+            target.set_record_level(record.level());
+            self.write_record(&mut target, record, info);
+        }
+
+        self.write_record_to_routes(record, info);
+    }
+
+    /// Write a record to the level routes that accept the record's level.
+    fn write_record_to_routes(&self, record: &log::Record, info: &RecordInfo) {
+        for (levels, target) in &self.level_routes {
+            if levels.matches(record.level()) {
+                let mut target = match target.lock() {
+                    Ok(target) => target,
+                    Err(err) => err.into_inner(),
+                };
+                target.set_record_level(record.level());
+                self.write_record(&mut target, record, info);
+            }
+        }
+    }
+
+    /// The ID shown for `thread`, which should be the current thread. This is the operating
+    /// system's thread ID if that was enabled and it's available, and Rust's thread ID otherwise.
+    fn thread_id(&self, thread: &std::thread::Thread) -> String {
+        match os_thread_id().filter(|_| self.os_thread_id) {
+            Some(id) => id.to_string(),
+            None => thread_id(thread),
+        }
+    }
+
+    /// Write the current thread according to `self.thread_format`. Returns whether anything was
+    /// written.
+    fn write_thread(&self, writer: &mut CountingWriter) -> bool {
+        if self.thread_format == ThreadFormat::None {
+            return false;
+        }
+
+        let current_thread = std::thread::current();
+        let id = self.thread_id(&current_thread);
+
+        // Thread names can be useful for added context, but the default main thread doesn't carry
+        // any special meaning and this can be deduced from the thread ID anyways. Unless the user
+        // explicitly asked for it to be shown, that is.
+        let name = current_thread
+            .name()
+            .filter(|name| self.show_main_thread_name || *name != "main");
+        if name.is_none() && self.hide_unnamed_threads {
+            return false;
+        }
+
+        let _ = match (self.thread_format, name) {
+            (ThreadFormat::NameOnly, Some(name)) => write!(writer, "({name})"),
+            (ThreadFormat::IdAndName, Some(name)) => write!(writer, "({id}, {name})"),
+            _ => write!(writer, "({id})"),
+        };
+
+        true
+    }
+
+    /// Write the record's module path, and optionally its target, if they should be shown.
+    /// Prefixes the output with a space if `leading_space` is set. Returns whether anything was
+    /// written.
+    fn write_module_path(
+        &self,
+        writer: &mut CountingWriter,
+        record: &log::Record,
+        leading_space: bool,
+    ) -> bool {
+        let module_path = record
+            .module_path()
+            .filter(|module_path| self.module_path_shown(module_path));
+        // The target is the module path by default, so it's only interesting when it differs
+        let target = Some(record.target())
+            .filter(|target| self.show_target && Some(*target) != record.module_path());
+
+        if module_path.is_none() && target.is_none() {
+            return false;
+        }
+
+        if leading_space {
+            let _ = write!(writer, " ");
+        }
+        with_color(writer, self.theme.module_path, |writer| {
+            let _ = match (module_path, target) {
+                (Some(module_path), Some(target)) => write!(writer, "{module_path} ({target})"),
+                (Some(module_path), None) => write!(writer, "{module_path}"),
+                (None, Some(target)) => write!(writer, "({target})"),
+                (None, None) => Ok(()),
+            };
+        });
+
+        true
+    }
+
+    /// Write a record as a single line of `key=value` pairs in the logfmt format. The fields are
+    /// the timestamp, the log level, the target, the thread, the source location, and the message.
+    fn write_logfmt(&self, writer: &mut CountingWriter, record: &log::Record, info: &RecordInfo) {
+        if self.timestamps {
+            let _ = write!(writer, "ts=");
+            let _ = info.time.format_into(&mut *writer, &Rfc3339);
+            if let Some(delta) = info.interline_delta {
+                let _ = write!(writer, " delta={:.3}s", delta.as_secs_f64());
+            }
+            let _ = write!(writer, " ");
+        }
+        let _ = write!(
+            writer,
+            "level={}",
+            record.level().as_str().to_ascii_lowercase()
+        );
+
+        let _ = write!(writer, " target=");
+        write_logfmt_value(writer, record.target());
+
+        let current_thread = std::thread::current();
+        let _ = write!(writer, " thread={}", self.thread_id(&current_thread));
+        if let Some(name) = current_thread.name() {
+            let _ = write!(writer, " thread_name=");
+            write_logfmt_value(writer, name);
+        }
+
+        if let Some(file) = record.file() {
+            let _ = write!(writer, " file=");
+            write_logfmt_value(writer, file);
+        }
+        if let Some(line) = record.line() {
+            let _ = write!(writer, " line={line}");
+        }
+
+        let _ = write!(writer, " msg=");
+        self.with_formatted_message(record.args(), |message| write_logfmt_value(writer, message));
+
+        context::for_each_pair(&info.fields, |key, value| {
+            let _ = write!(writer, " {key}=");
+            write_logfmt_value(writer, value);
+        });
+
+        if !info.diff.is_empty() {
+            let mut diff = String::new();
+            for (kind, line) in &info.diff {
+                if !diff.is_empty() {
+                    diff.push('\n');
+                }
+                diff.push_str(kind.prefix());
+                diff.push_str(line);
+            }
+            let _ = write!(writer, " diff=");
+            write_logfmt_value(writer, &diff);
+        }
+
+        if let Some(backtrace) = &info.backtrace {
+            let _ = write!(writer, " backtrace=");
+            write_logfmt_value(writer, backtrace);
+        }
+        let _ = writeln!(writer);
+    }
+
+    /// Format a record, including the trailing newline, using the configured format. This only
+    /// writes to `writer` and doesn't flush it, so it can also be used to format records into
+    /// memory. Flushing and keeping track of the number of bytes written is done in
+    /// [`write_record()`][Self::write_record()].
+    fn format_record(&self, writer: &mut CountingWriter, record: &log::Record, info: &RecordInfo) {
         // The log message consists of the following elements:
-        // 1) The current time in `hh:mm:ss`
+        // 1) (unless disabled) The current time in `hh:mm:ss`, optionally followed by the time since
+        //    the previous record
         // 2) The log level, colored if colors are enabled
         // 3) (only on the debug and trace levels) The ID of the current thread
         // 4) (only on the debug and trace levels) The crate and module path
         // 5) (only on the trace level) The file name and line number
         // 6) The actual log message
+        // 7) Any key-value pairs from the current thread's context
+        // 8) (only for `diff!()`) A diff between two values on the following lines
+        // 9) (only when enabled for the record's level) A backtrace on the following lines
         // TODO: We silently ignore failing writes and flushes. Is there anything reasonable we can
         //       do here other than panicking? (which isn't super reasonable)
-        let current_time = time::OffsetDateTime::now_utc().to_offset(self.local_time_offset);
-        let _ = current_time.format_into(&mut writer, TIME_FORMAT_DESCRIPTION);
+        let message_only = self.message_only || (self.plain_info && record.level() == Level::Info);
+        if let Some(line_prefix) = &self.line_prefix {
+            let _ = write!(writer, "{line_prefix}");
+        }
+        if let Some(sequence_number) = info.sequence_number {
+            let _ = if self.logfmt {
+                write!(writer, "seq={sequence_number} ")
+            } else if message_only {
+                write!(writer, "#{sequence_number} ")
+            } else {
+                write!(writer, "#{sequence_number}{}", self.column_separator)
+            };
+        }
+
+        if message_only {
+            // In this mode all of the above except for the message itself is left to whatever is
+            // displaying the log output
+            self.write_message_body(writer, record.args());
+            write_diff(writer, &info.diff);
+            let _ = writeln!(writer);
+
+            return;
+        }
+
+        if self.logfmt {
+            self.write_logfmt(writer, record, info);
+
+            return;
+        }
 
         // If `writer` is a STDERR stream that outputs to a terminal with color support, we can
-        // colorize the log message
-        match record.level() {
-            log::Level::Error => {
-                writer.set_fg_color(Color::Red);
-                let _ = write!(writer, " [ERROR] ");
-                writer.reset_colors();
-            }
-            log::Level::Warn => {
-                writer.set_fg_color(Color::Yellow);
-                let _ = write!(writer, " [WARN] ");
-                writer.reset_colors();
-            }
-            log::Level::Info => {
-                writer.set_fg_color(Color::Blue);
-                let _ = write!(writer, " [INFO] ");
-                writer.reset_colors();
-            }
-            log::Level::Debug => {
-                writer.set_fg_color(Color::Cyan);
-                let _ = write!(writer, " [DEBUG] ");
-                writer.reset_colors();
-            }
-            log::Level::Trace => {
-                let _ = write!(writer, " [TRACE] ");
-            }
-        }
-
-        if record.level() >= Level::Debug {
-            let current_thread = std::thread::current();
-
-            // `TreadId::as_u64()` is still unstable, so we'll work around this parsing the `Debug`
-            // representation
-            let id = format!("{:?}", current_thread.id());
-            let id = id
-                .strip_prefix("ThreadId(")
-                .and_then(|id| id.strip_suffix(')'))
-                .unwrap_or(&id);
-
-            let _ = match current_thread.name() {
-                // Thread names can be useful for added context, but the default main thread doesn't
-                // carry any special meaning and this can be deduced from the thread ID anyways
-                Some(name) if name != "main" => write!(writer, "({id}, {name})"),
-                _ => write!(writer, "({id})"),
+        // colorize the log message using the theme's colors. If setting a color fails, then the
+        // writer resets the colors and the rest of the record is written as plain text.
+        if self.timestamps {
+            with_color(writer, self.theme.timestamp, |writer| {
+                if self.monotonic_timestamps {
+                    let time = monotonic_time(self.start_time);
+                    let _ = write!(writer, "{}.{:09}", time.as_secs(), time.subsec_nanos());
+                } else {
+                    let _ = match &self.time_format {
+                        Some(time_format) => info.time.format_into(writer, time_format),
+                        None => info.time.format_into(writer, TIME_FORMAT_DESCRIPTION),
+                    };
+                }
+                if let Some(delta) = info.interline_delta {
+                    let _ = write!(writer, " +{:.3}s", delta.as_secs_f64());
+                }
+            });
+            let _ = write!(writer, "{}", self.column_separator);
+        }
+
+        let tag = &self.level_labels[record.level() as usize - 1];
+        if writer.writes_ansi() {
+            // This avoids building the same escape sequences over and over again
+            let tag = &self.ansi_level_tags[record.level() as usize - 1];
+            let _ = writer.write_with_escapes(&tag.bytes, tag.visible_chars);
+        } else {
+            with_color(writer, self.theme.level_color(record.level()), |writer| {
+                let _ = write!(writer, "{tag}");
+            });
+        }
+        let padding = self.level_tag_width.saturating_sub(tag.chars().count());
+        if padding > 0 {
+            let _ = write!(writer, "{:padding$}", "");
+        }
+        let _ = write!(writer, "{}", self.column_separator);
+
+        // The thread and the module path are separated by a space, and the two together are
+        // followed by a colon and the column separator. This is decided in one place so the
+        // spacing is the same regardless of which parts are shown.
+        let is_verbose = record.level() >= Level::Debug;
+        let wrote_thread = is_verbose && self.write_thread(writer);
+        let wrote_module_path = (is_verbose || self.always_show_module_path)
+            && self.write_module_path(writer, record, wrote_thread);
+        if wrote_thread || wrote_module_path {
+            let _ = write!(writer, ":{}", self.column_separator);
+        }
+
+        if record.level() >= Level::Trace {
+            // Terminals that support OSC 8 hyperlinks can open these locations when they're
+            // wrapped in a `file://` link. Since the paths from the `file!()` macro are relative,
+            // we'll resolve them relative to the current working directory.
+            let link = match record.file() {
+                Some(file) if self.clickable_locations && writer.supports_color() => {
+                    std::env::current_dir().ok().map(|dir| dir.join(file))
+                }
+                _ => None,
             };
 
-            if let Some(module_path) = record.module_path() {
-                let _ = write!(writer, " {}", module_path);
+            if let Some(link) = &link {
+                let _ = writer
+                    .write_escape_sequence(format_args!("\x1b]8;;file://{}\x1b\\", link.display()));
+            }
+            with_color(writer, self.theme.location, |writer| {
+                let _ = match (record.file(), record.line()) {
+                    (Some(file), Some(line)) => write!(writer, "[{file}:{line}]"),
+                    (Some(file), None) => write!(writer, "[{file}]"),
+                    _ => Ok(()),
+                };
+            });
+            if link.is_some() {
+                let _ = writer.write_escape_sequence(format_args!("\x1b]8;;\x1b\\"));
             }
+            if record.file().is_some() {
+                let _ = write!(writer, "{}", self.column_separator);
+            }
+        }
 
-            let _ = write!(writer, ": ");
-        } else if self.always_show_module_path {
-            // The spacing is a bit different without a thread name, hence the else if here
-            if let Some(module_path) = record.module_path() {
-                let _ = write!(writer, "{}: ", module_path);
+        let highlighted = self.module_path_highlighted(
+            record
+                .module_path()
+                .unwrap_or_else(|| record.metadata().target()),
+        );
+        let highlight_bold = highlighted && writer.writes_ansi();
+        if highlighted {
+            let _ = write!(writer, "{HIGHLIGHT_MARKER}");
+            if highlight_bold {
+                let _ = writer.write_escape_sequence(format_args!("\x1b[1m"));
             }
         }
 
-        if record.level() >= Level::Trace {
-            let _ = match (record.file(), record.line()) {
-                (Some(file), Some(line)) => write!(writer, "[{file}:{line}] "),
-                (Some(file), None) => write!(writer, "[{file}] "),
-                _ => Ok(()),
-            };
+        if self.multiline_indent || self.wrap_width.is_some() {
+            // Continuation lines are indented so they line up with the first line of the message.
+            // This requires formatting the message up front to find the line breaks.
+            let indent = writer.visible_chars_written() as usize;
+            let max_line_chars = self.wrap_width.map(|width| max_line_chars(width, indent));
+            self.with_formatted_message(record.args(), |message| {
+                let message = if self.normalize_newlines {
+                    message.strip_suffix('\n').unwrap_or(message)
+                } else {
+                    message
+                };
+                let mut lines = message
+                    .split('\n')
+                    .flat_map(|line| wrap_line(line, max_line_chars));
+                if let Some(first_line) = lines.next() {
+                    let _ = write!(writer, "{first_line}");
+                }
+                for line in lines {
+                    let _ = write!(writer, "\n{:indent$}{line}", "");
+                }
+            });
+        } else {
+            self.write_message_body(writer, record.args());
         }
 
-        let _ = writeln!(writer, "{}", record.args());
+        if highlight_bold {
+            let _ = writer.write_escape_sequence(format_args!("\x1b[22m"));
+        }
 
-        // Every line should be flushed immediately to avoid surprises
-        let _ = writer.flush();
-    }
-}
+        context::for_each_pair(&info.fields, |key, value| {
+            let _ = write!(writer, " {key}={value}");
+        });
 
-impl Log for Logger {
-    fn enabled(&self, metadata: &log::Metadata) -> bool {
-        metadata.level() <= self.max_log_level && !self.target_enabled(metadata.target())
+        write_diff(writer, &info.diff);
+
+        if let Some(backtrace) = &info.backtrace {
+            let _ = write!(writer, "\n{backtrace}");
+        }
+
+        // The colors are already reset after every colored part of the line, but if the message
+        // itself contains escape sequences or if a write was interrupted halfway then the terminal
+        // may otherwise be left in a colored state
+        if writer.supports_color() {
+            writer.reset_colors();
+        }
+        let _ = writeln!(writer);
     }
 
-    fn log(&self, record: &log::Record) {
+    /// The implementation of [`Log::log()`]. If `time` is set, then the record is written with
+    /// that time instead of the current time. This is used to replay records that were logged
+    /// before the logger was installed.
+    pub fn log_at(&self, record: &log::Record, time: Option<time::OffsetDateTime>) {
+        if self.paused.load(Ordering::Relaxed) {
+            return;
+        }
+
+        // Formatting the message may log other records, which should not get this record's fields
+        // and diff
+        let fields = kv::take_fields();
+        let diff = diff::take_lines();
+
+        // The chained logger does its own filtering, so NIH-log's filters don't apply to it
+        if let Some(chained_logger) = &self.chained_logger {
+            if chained_logger.enabled(record.metadata()) {
+                chained_logger.log(record);
+            }
+        }
+
+        // Records above this level are normally compiled out, but they can still reach the logger
+        // when they're constructed manually
+        if record.level() > log::STATIC_MAX_LEVEL {
+            return;
+        }
+
         if !self.target_enabled(
             record
                 .module_path()
                 .unwrap_or_else(|| record.metadata().target()),
-        ) {
+        ) || !self.filter_fn_enabled(record.metadata())
+        {
+            return;
+        }
+
+        // With signal control or an error context ring the `log` crate lets every record through,
+        // see `facade_max_level()`
+        self.apply_signal_adjustments();
+        if record.level() > self.effective_max_level() {
+            self.push_error_context(record, fields, diff);
+            return;
+        }
+
+        self.call_record_hook(record);
+
+        let mut info = self.record_info(record.level(), fields, diff);
+        if let Some(time) = time {
+            info.time = time.to_offset(self.local_time_offset);
+        }
+
+        if !self.reentrancy_guard {
+            self.write_record_to_targets(record, &info, true);
+
             return;
         }
 
@@ -161,10 +1464,14 @@ impl Log for Logger {
         // since this won't occur in any other situation.q
         IS_REENTRANT_LOGGING_CALL.with(|is_reentrant_logging_call| {
             if is_reentrant_logging_call.get() {
+                self.report_reentrant_call(record);
+
                 // This will also allocate, but `assert_no_alloc` allows allocations in its
                 // allocation failure handler
-                let mut target = OutputTargetImpl::default_from_environment();
-                self.do_log(target.writer(), record);
+                let mut target = OutputTargetImpl::default_from_environment(self.create_log_dirs)
+                    .with_color_mode(self.color_mode);
+                target.set_record_level(record.level());
+                self.write_record(&mut target, record, &info);
             } else {
                 is_reentrant_logging_call.set(true);
 
@@ -172,23 +1479,465 @@ impl Log for Logger {
                 // raised are allocation failures from `assert_no_alloc`, and we already reserve
                 // quite a bit of capacity to prevent additional allocations (though this as a whole
                 // of course still isn't realtime-safe)
-                let mut target = match self.output_target.lock() {
-                    Ok(target) => target,
-                    Err(err) => err.into_inner(),
-                };
-                self.do_log(target.writer(), record);
+                self.write_record_to_targets(record, &info, true);
 
                 is_reentrant_logging_call.set(false);
             }
         });
     }
+}
+
+/// The number of characters of the message that fit on a line when wrapping at `width` columns
+/// with `indent` columns of context before the message. This is always at least one, even if the
+/// context alone is wider than `width`.
+fn max_line_chars(width: usize, indent: usize) -> usize {
+    width.saturating_sub(indent).max(1)
+}
+
+/// Split a line into chunks of at most `max_chars` characters. Yields the line as is if
+/// `max_chars` is not set or if the line is short enough. Empty lines are also yielded. Every
+/// chunk contains at least one character, so a `max_chars` of zero is treated as one.
+fn wrap_line(line: &str, max_chars: Option<usize>) -> impl Iterator<Item = &str> {
+    let mut remaining = Some(line);
+    std::iter::from_fn(move || {
+        let line = remaining?;
+        let split_index = max_chars
+            .and_then(|max_chars| line.char_indices().nth(max_chars.max(1)))
+            .map(|(index, _)| index);
+        match split_index {
+            Some(index) => {
+                remaining = Some(&line[index..]);
+                Some(&line[..index])
+            }
+            None => {
+                remaining = None;
+                Some(line)
+            }
+        }
+    })
+}
+
+/// The default tag written after the timestamp for a log level. See
+/// [`LoggerBuilder::with_level_label()`][crate::LoggerBuilder::with_level_label()].
+pub fn level_tag(level: Level) -> &'static str {
+    match level {
+        Level::Error => "[ERROR]",
+        Level::Warn => "[WARN]",
+        Level::Info => "[INFO]",
+        Level::Debug => "[DEBUG]",
+        Level::Trace => "[TRACE]",
+    }
+}
+
+/// Call `f` to write something in `color`, if a color is set and the writer supports colors.
+fn with_color(
+    writer: &mut CountingWriter,
+    color: Option<Color>,
+    f: impl FnOnce(&mut CountingWriter),
+) {
+    match color {
+        Some(color) if writer.supports_color() => {
+            let _ = writer.set_fg_color(color);
+            f(writer);
+            writer.reset_colors();
+        }
+        _ => f(writer),
+    }
+}
+
+/// Write the lines of the diff attached to a record by [`diff!()`][crate::diff!()], if any, each
+/// on their own line. Removed lines are red and added lines are green if the writer supports
+/// colors.
+fn write_diff(writer: &mut CountingWriter, diff: &[(DiffLine, String)]) {
+    for (kind, line) in diff {
+        let kind = *kind;
+        let _ = writeln!(writer);
+        let color = match kind {
+            DiffLine::Unchanged => None,
+            DiffLine::Removed => Some(Color::Red),
+            DiffLine::Added => Some(Color::Green),
+        };
+        with_color(writer, color, |writer| {
+            let _ = write!(writer, "{}{line}", kind.prefix());
+        });
+    }
+}
+
+/// Get a thread's ID as a string.
+pub fn thread_id(thread: &std::thread::Thread) -> String {
+    // `TreadId::as_u64()` is still unstable, so we'll work around this parsing the `Debug`
+    // representation
+    let id = format!("{:?}", thread.id());
+    match id
+        .strip_prefix("ThreadId(")
+        .and_then(|id| id.strip_suffix(')'))
+    {
+        Some(stripped_id) => stripped_id.to_owned(),
+        None => id,
+    }
+}
+
+/// Write a record's message to `writer`. `io::Write::write_fmt()` panics when a `Display`
+/// implementation returns an error while the underlying stream did not, so this formats the message
+/// through `std::fmt::write()` instead. If formatting fails, then the part of the message that was
+/// already written is followed by [`MESSAGE_FORMATTING_FAILED`].
+fn write_message(writer: &mut CountingWriter, args: &std::fmt::Arguments) {
+    /// Keeps track of whether an error came from the writer or from the message's formatting.
+    struct Adapter<'a, 'b> {
+        writer: &'a mut CountingWriter<'b>,
+        io_failed: bool,
+    }
+
+    impl std::fmt::Write for Adapter<'_, '_> {
+        fn write_str(&mut self, s: &str) -> std::fmt::Result {
+            self.writer.write_all(s.as_bytes()).map_err(|_| {
+                self.io_failed = true;
+                std::fmt::Error
+            })
+        }
+    }
+
+    let mut adapter = Adapter {
+        writer,
+        io_failed: false,
+    };
+    if std::fmt::write(&mut adapter, *args).is_err() && !adapter.io_failed {
+        let _ = write!(adapter.writer, "{MESSAGE_FORMATTING_FAILED}");
+    }
+}
+
+/// Append a message to `buffer`. If one of the message's `Display` implementations returns an
+/// error, then the part of the message that was already formatted is followed by
+/// [`MESSAGE_FORMATTING_FAILED`]. Unlike `to_string()`, this never panics.
+pub fn format_message(buffer: &mut String, args: &std::fmt::Arguments) {
+    if std::fmt::Write::write_fmt(buffer, *args).is_err() {
+        buffer.push_str(MESSAGE_FORMATTING_FAILED);
+    }
+}
+
+/// The current value of `CLOCK_MONOTONIC` on Linux, which is the clock used for the kernel log's
+/// timestamps. On other platforms, or if the clock could not be read, this is the time passed since
+/// `start_time`.
+fn monotonic_time(start_time: Instant) -> Duration {
+    #[cfg(target_os = "linux")]
+    {
+        let mut timespec = libc::timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+        if unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut timespec) } == 0 {
+            return Duration::new(timespec.tv_sec as u64, timespec.tv_nsec as u32);
+        }
+    }
+
+    start_time.elapsed()
+}
+
+/// The operating system's ID for the current thread, if it can be queried on this platform.
+fn os_thread_id() -> Option<u64> {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    {
+        Some(unsafe { libc::syscall(libc::SYS_gettid) } as u64)
+    }
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    {
+        // A null thread handle refers to the current thread
+        let mut id: u64 = 0;
+        match unsafe { libc::pthread_threadid_np(0, &mut id) } {
+            0 => Some(id),
+            _ => None,
+        }
+    }
+    #[cfg(windows)]
+    {
+        Some(unsafe { windows::Win32::System::Threading::GetCurrentThreadId() } as u64)
+    }
+    #[cfg(not(any(
+        target_os = "linux",
+        target_os = "android",
+        target_os = "macos",
+        target_os = "ios",
+        windows
+    )))]
+    {
+        None
+    }
+}
+
+/// Replace the current thread's output target, returning the previous one. See
+/// [`crate::set_thread_target()`].
+pub fn replace_thread_target(target: Option<OutputTargetImpl>) -> Option<OutputTargetImpl> {
+    THREAD_TARGET.with(|thread_target| thread_target.replace(target))
+}
+
+/// Write a value for the logfmt format. Values containing spaces, equals signs, quotes, or control
+/// characters are quoted and escaped.
+fn write_logfmt_value(writer: &mut CountingWriter, value: &str) {
+    let needs_quoting = value.is_empty()
+        || value
+            .chars()
+            .any(|c| c == ' ' || c == '=' || c == '"' || c.is_control());
+    if !needs_quoting {
+        let _ = write!(writer, "{value}");
+        return;
+    }
+
+    let _ = write!(writer, "\"");
+    for c in value.chars() {
+        let _ = match c {
+            '"' => write!(writer, "\\\""),
+            '\\' => write!(writer, "\\\\"),
+            '\n' => write!(writer, "\\n"),
+            '\r' => write!(writer, "\\r"),
+            '\t' => write!(writer, "\\t"),
+            c => write!(writer, "{c}"),
+        };
+    }
+    let _ = write!(writer, "\"");
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        if let Some(enabled) = answer_version_probe(metadata) {
+            return enabled;
+        }
+
+        if self.paused.load(Ordering::Relaxed) {
+            return false;
+        }
+
+        if let Some(chained_logger) = &self.chained_logger {
+            if chained_logger.enabled(metadata) {
+                return true;
+            }
+        }
+
+        // `log::STATIC_MAX_LEVEL` is a constant, so this check is free when the level is not
+        // statically capped
+        metadata.level() <= log::STATIC_MAX_LEVEL
+            && metadata.level() <= self.effective_max_level()
+            && self.target_enabled(metadata.target())
+            && self.filter_fn_enabled(metadata)
+    }
+
+    fn log(&self, record: &log::Record) {
+        self.log_at(record, None);
+    }
 
     fn flush(&self) {
-        let _ = self
-            .output_target
-            .lock()
-            .expect("Mutex poisoned")
-            .writer()
-            .flush();
+        let _ = self.output_target.lock().expect("Mutex poisoned").flush();
+        for (_, target) in &self.level_routes {
+            let _ = target.lock().expect("Mutex poisoned").flush();
+        }
+        let _ = THREAD_TARGET.try_with(|thread_target| {
+            if let Ok(Some(target)) = thread_target.try_borrow_mut().as_deref_mut() {
+                let _ = target.flush();
+            }
+        });
+
+        if let Some(chained_logger) = &self.chained_logger {
+            chained_logger.flush();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{LoggerBuilder, OffsetFailureAction};
+
+    /// Build a logger that writes to a ring buffer without installing it.
+    fn ring_buffer_logger(builder: LoggerBuilder) -> Logger {
+        builder
+            .on_offset_failure(OffsetFailureAction::SilentUtc)
+            .with_output_target(OutputTarget::RingBuffer { size: 4096 })
+            .unwrap()
+            .build()
+            .unwrap()
+            .0
+    }
+
+    /// Everything written to a logger from [`ring_buffer_logger()`] so far.
+    fn ring_buffer_text(logger: &Logger) -> String {
+        String::from_utf8(logger.ring_buffer_contents().unwrap()).unwrap()
+    }
+
+    fn wrap(line: &str, max_chars: Option<usize>) -> Vec<&str> {
+        wrap_line(line, max_chars).collect()
+    }
+
+    #[test]
+    fn wrap_line_without_width() {
+        assert_eq!(wrap("a long line", None), ["a long line"]);
+        assert_eq!(wrap("", None), [""]);
+        assert_eq!(wrap("", Some(4)), [""]);
+    }
+
+    #[test]
+    fn wrap_line_chunks() {
+        assert_eq!(wrap("abcdefghij", Some(4)), ["abcd", "efgh", "ij"]);
+        assert_eq!(wrap("abcdefgh", Some(4)), ["abcd", "efgh"]);
+        assert_eq!(wrap("abc", Some(4)), ["abc"]);
+    }
+
+    #[test]
+    fn wrap_line_multibyte_boundary() {
+        // The split falls right before and right after two-byte and four-byte characters
+        assert_eq!(wrap("abcéfg", Some(3)), ["abc", "éfg"]);
+        assert_eq!(wrap("abéd", Some(3)), ["abé", "d"]);
+        assert_eq!(wrap("é🦀é🦀é", Some(2)), ["é🦀", "é🦀", "é"]);
+    }
+
+    #[test]
+    fn wrap_line_zero_width() {
+        assert_eq!(wrap("abc", Some(0)), ["a", "b", "c"]);
+        assert_eq!(max_line_chars(0, 0), 1);
+        assert_eq!(max_line_chars(0, 12), 1);
+    }
+
+    #[test]
+    fn wrap_width_smaller_than_prefix() {
+        // Every line still contains at least one character of the message
+        assert_eq!(max_line_chars(10, 20), 1);
+        assert_eq!(max_line_chars(10, 10), 1);
+        assert_eq!(max_line_chars(30, 20), 10);
+        assert_eq!(wrap("abc", Some(max_line_chars(10, 20))), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn env_warnings_bypass_filters() {
+        let mut logger = ring_buffer_logger(
+            LoggerBuilder::new(LevelFilter::Off)
+                .deterministic()
+                .quiet()
+                .filter_crate("nih_log")
+                .with_filter_fn(Box::new(|_| false)),
+        );
+        logger.env_config.warnings = vec![String::from("Could not open 'missing.log'")];
+        logger.paused.store(true, Ordering::Relaxed);
+
+        logger.log_env_warnings();
+        assert_eq!(
+            ring_buffer_text(&logger),
+            "[WARN] nih_log::logger: Could not open 'missing.log'\n"
+        );
+    }
+
+    #[test]
+    fn multiline_indent_counts_characters() {
+        let logger = ring_buffer_logger(
+            LoggerBuilder::new(LevelFilter::Debug)
+                .deterministic()
+                .with_thread_format(ThreadFormat::NameOnly)
+                .with_multiline_indent(),
+        );
+
+        let formatted = std::thread::Builder::new()
+            .name(String::from("wörker"))
+            .spawn(move || {
+                logger.format_preview(
+                    &log::Record::builder()
+                        .args(format_args!("first\nsecond"))
+                        .level(Level::Debug)
+                        .target("target")
+                        .build(),
+                )
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+
+        assert_eq!(
+            formatted,
+            "[DEBUG] (wörker): first\n                  second\n"
+        );
+    }
+
+    #[test]
+    fn wrap_at_counts_characters() {
+        let logger = ring_buffer_logger(
+            LoggerBuilder::new(LevelFilter::Info)
+                .deterministic()
+                .with_line_prefix("»» ")
+                .wrap_at(16),
+        );
+
+        let formatted = logger.format_preview(
+            &log::Record::builder()
+                .args(format_args!("abcdefghij"))
+                .level(Level::Info)
+                .target("target")
+                .build(),
+        );
+        assert_eq!(formatted, "»» [INFO] abcdef\n          ghij\n");
+    }
+
+    #[test]
+    fn filter_fn_drops_records() {
+        let logger = ring_buffer_logger(
+            LoggerBuilder::new(LevelFilter::Trace)
+                .deterministic()
+                .with_filter_fn(Box::new(|metadata| {
+                    metadata.target() != "noisy" && metadata.level() <= Level::Debug
+                })),
+        );
+
+        for (target, level) in [
+            ("noisy", Level::Error),
+            ("quiet", Level::Debug),
+            ("quiet", Level::Trace),
+        ] {
+            let metadata = log::Metadata::builder().target(target).level(level).build();
+            let expected = target == "quiet" && level == Level::Debug;
+            assert_eq!(log::Log::enabled(&logger, &metadata), expected);
+
+            log::Log::log(
+                &logger,
+                &log::Record::builder()
+                    .args(format_args!("from {target}"))
+                    .level(level)
+                    .target(target)
+                    .module_path(Some("my_crate"))
+                    .build(),
+            );
+        }
+
+        assert_eq!(ring_buffer_text(&logger), "[DEBUG] my_crate: from quiet\n");
+    }
+
+    #[test]
+    fn logfmt_output() {
+        let logger = ring_buffer_logger(
+            LoggerBuilder::new(LevelFilter::Info)
+                .deterministic()
+                .with_logfmt(),
+        );
+
+        let (formatted, thread) = std::thread::Builder::new()
+            .name(String::from("audio thread"))
+            .spawn(move || {
+                let formatted = logger.format_preview(
+                    &log::Record::builder()
+                        .args(format_args!("said \"hi\"\nand left"))
+                        .level(Level::Warn)
+                        .target("my_crate::audio")
+                        .file(Some("src/audio.rs"))
+                        .line(Some(12))
+                        .build(),
+                );
+
+                (formatted, thread_id(&std::thread::current()))
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+
+        assert_eq!(
+            formatted,
+            format!(
+                "level=warn target=my_crate::audio thread={thread} thread_name=\"audio thread\" \
+                 file=src/audio.rs line=12 msg=\"said \\\"hi\\\"\\nand left\"\n"
+            )
+        );
     }
 }
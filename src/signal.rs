@@ -0,0 +1,127 @@
+//! Adjusting the log level at runtime through Unix signals. See
+//! [`LoggerBuilder::with_signal_control()`][crate::LoggerBuilder::with_signal_control()].
+
+use log::LevelFilter;
+use std::sync::atomic::{AtomicI32, AtomicIsize, Ordering};
+
+/// The signal that increases the log level's verbosity. Zero if no handler has been installed.
+static INCREASE_SIGNAL: AtomicI32 = AtomicI32::new(0);
+/// The signal that decreases the log level's verbosity. Zero if no handler has been installed.
+static DECREASE_SIGNAL: AtomicI32 = AtomicI32::new(0);
+/// The number of levels the verbosity should be adjusted by, accumulated by the signal handler
+/// until the logger applies it on the next log call.
+static PENDING_ADJUSTMENT: AtomicIsize = AtomicIsize::new(0);
+
+/// Install signal handlers for `increase_signal` and `decrease_signal`. Replaces any handlers that
+/// were previously installed for these signals. Returns an error if both signals are the same.
+pub fn install_handlers(increase_signal: i32, decrease_signal: i32) -> std::io::Result<()> {
+    if increase_signal == decrease_signal {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "the signals for increasing and decreasing the log level must be different",
+        ));
+    }
+
+    INCREASE_SIGNAL.store(increase_signal, Ordering::Relaxed);
+    DECREASE_SIGNAL.store(decrease_signal, Ordering::Relaxed);
+
+    for signal in [increase_signal, decrease_signal] {
+        // SAFETY: `handle_signal()` only touches atomics, which is async-signal-safe
+        unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = handle_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+            action.sa_flags = libc::SA_RESTART;
+            libc::sigemptyset(&mut action.sa_mask);
+            if libc::sigaction(signal, &action, std::ptr::null_mut()) != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Take the verbosity adjustment accumulated since the last call. Positive values mean more
+/// verbose.
+pub fn take_pending_adjustment() -> isize {
+    // Avoid the read-modify-write on the hot path
+    if PENDING_ADJUSTMENT.load(Ordering::Relaxed) == 0 {
+        0
+    } else {
+        PENDING_ADJUSTMENT.swap(0, Ordering::Relaxed)
+    }
+}
+
+/// Add `pending_adjustment` to the current adjustment of `base_level`, and clamp the result so the
+/// adjusted level stays between [`LevelFilter::Off`] and `max_level`. Returns the new adjustment.
+/// Clamping makes a change in the opposite direction take effect immediately, even after the
+/// process was sent more signals than there are levels.
+pub fn clamp_adjustment(
+    base_level: LevelFilter,
+    max_level: LevelFilter,
+    current_adjustment: isize,
+    pending_adjustment: isize,
+) -> isize {
+    let base_level = base_level as isize;
+    let adjusted_level =
+        (base_level + current_adjustment + pending_adjustment).clamp(0, max_level as isize);
+
+    adjusted_level - base_level
+}
+
+/// The signal handler. This may run at any point on any thread, so it must not allocate, lock, or
+/// do any I/O. The logger picks up the adjustment the next time something is logged.
+extern "C" fn handle_signal(signal: libc::c_int) {
+    if signal == INCREASE_SIGNAL.load(Ordering::Relaxed) {
+        PENDING_ADJUSTMENT.fetch_add(1, Ordering::Relaxed);
+    } else if signal == DECREASE_SIGNAL.load(Ordering::Relaxed) {
+        PENDING_ADJUSTMENT.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn adjustment_clamped_at_trace() {
+        let adjustment = clamp_adjustment(LevelFilter::Info, LevelFilter::Trace, 0, 10);
+        assert_eq!(adjustment, 2);
+
+        // A single decrease takes effect immediately after too many increases
+        let adjustment = clamp_adjustment(LevelFilter::Info, LevelFilter::Trace, adjustment, -1);
+        assert_eq!(adjustment, 1);
+    }
+
+    #[test]
+    fn adjustment_clamped_at_off() {
+        let adjustment = clamp_adjustment(LevelFilter::Info, LevelFilter::Trace, 0, -10);
+        assert_eq!(adjustment, -3);
+
+        let adjustment = clamp_adjustment(LevelFilter::Info, LevelFilter::Trace, adjustment, 1);
+        assert_eq!(adjustment, -2);
+    }
+
+    #[test]
+    fn adjustment_clamped_at_static_max_level() {
+        assert_eq!(
+            clamp_adjustment(LevelFilter::Info, LevelFilter::Debug, 0, 5),
+            1
+        );
+    }
+
+    #[test]
+    fn pending_adjustment_taken_once() {
+        assert!(install_handlers(libc::SIGUSR1, libc::SIGUSR1).is_err());
+
+        INCREASE_SIGNAL.store(libc::SIGUSR1, Ordering::Relaxed);
+        DECREASE_SIGNAL.store(libc::SIGUSR2, Ordering::Relaxed);
+        handle_signal(libc::SIGUSR1);
+        handle_signal(libc::SIGUSR1);
+        handle_signal(libc::SIGUSR2);
+        handle_signal(libc::SIGUSR1);
+
+        assert_eq!(take_pending_adjustment(), 2);
+        assert_eq!(take_pending_adjustment(), 0);
+    }
+}
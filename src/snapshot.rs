@@ -0,0 +1,35 @@
+//! A snapshot of the global logger's configuration. See
+//! [`config_snapshot()`][crate::config_snapshot()].
+
+use log::LevelFilter;
+use time::UtcOffset;
+
+/// The global logger's current configuration, gathered in a single struct. This is meant for
+/// debugging why the log looks the way it does, for instance by including the snapshot's `Debug`
+/// representation in a bug report. The output targets are described using their `Debug`
+/// representation, like `File("app.log")`. These descriptions are not stable and should not be
+/// parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigSnapshot {
+    /// The effective maximum log level across all output targets. See
+    /// [`max_level()`][crate::max_level()].
+    pub max_level: LevelFilter,
+    /// The effective maximum log level for the regular output target. Level routes may be more
+    /// verbose.
+    pub target_max_level: LevelFilter,
+    /// Whether quiet mode is currently enabled. See [`set_quiet()`][crate::set_quiet()].
+    pub quiet: bool,
+    /// Whether logging is currently paused. See [`pause()`][crate::pause()].
+    pub paused: bool,
+    /// The crates and modules excluded from the log, sorted alphabetically.
+    pub module_blacklist: Vec<String>,
+    /// Whether the module path is shown for every record and not just for debug and trace
+    /// records.
+    pub always_show_module_path: bool,
+    /// The local time offset used for timestamps.
+    pub local_time_offset: UtcOffset,
+    /// A description of the regular output target.
+    pub target: String,
+    /// A description of each level route's levels and output target.
+    pub level_routes: Vec<String>,
+}
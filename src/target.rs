@@ -1,40 +1,85 @@
 //! The logger's output targets.
 
+use log::Level;
 use std::fmt::Debug;
 use std::fs::File;
 use std::io::{BufWriter, Write};
 use std::path::Path;
-use termcolor::{BufferedStandardStream, Color, ColorChoice, ColorSpec, WriteColor};
+use std::sync::atomic::{AtomicBool, Ordering};
+use termcolor::{
+    BufferedStandardStream, Color, ColorChoice, ColorSpec, StandardStream, WriteColor,
+};
 
-#[cfg(windows)]
+use crate::builder::ColorMode;
+use crate::env::{EnvConfig, EnvTarget};
+
+mod atomic_file;
+#[cfg(all(target_arch = "wasm32", feature = "wasm-console"))]
+mod console;
+mod file;
+#[cfg(feature = "flate2")]
+mod gzip;
+mod ring_buffer;
+// The writer itself is platform independent, so its buffering can be tested everywhere
+#[cfg(any(windows, test))]
+#[cfg_attr(not(windows), allow(dead_code))]
 mod windbg;
 
-/// The environment variable for controlling the logging behavior.
-const NIH_LOG_ENV: &str = "NIH_LOG";
+/// Whether [`OutputTargetImpl::default_from_environment()`] has already printed the problems it
+/// ran into. A new target is created for every reentrant logging call, so a `NIH_LOG` file that
+/// can't be opened would otherwise print the same warning for every one of those calls.
+static ENV_WARNINGS_PRINTED: AtomicBool = AtomicBool::new(false);
 
 /// Similar to [`crate::builder::OutputTarget`], but contains the actual data needed to write to the
 /// logger.
-pub enum OutputTargetImpl {
+pub struct OutputTargetImpl {
+    writer: TargetWriter,
+    /// The number of bytes written since the writer was last flushed. Used to only flush after a
+    /// certain amount of output when a flush threshold is set.
+    unflushed_bytes: u64,
+}
+
+/// The writer for an [`OutputTargetImpl`].
+enum TargetWriter {
     /// The default logging target on Windows. This checks whether a Windows debugger is attached
     /// before logging. If there is a debugger, then the message is written using
     /// `OutputDebugString()`. Otherwise the message is written to STDERR instead.
     #[cfg(windows)]
-    StderrOrWinDbg(BufferedStandardStream, windbg::WinDbgWriter),
+    StderrOrWinDbg(StderrStream, windbg::WinDbgWriter),
     /// Writes directly to STDERR. The default logging target on non-Windows platforms. May use
     /// colors colors depending on the environment.
-    Stderr(BufferedStandardStream),
+    Stderr(StderrStream),
     /// Outputs to the Windows debugger using `OutputDebugString()`.
     #[cfg(windows)]
     WinDbg(windbg::WinDbgWriter),
-    /// Writes to the file.
-    File(BufWriter<File>),
+    /// Writes to the file. Colors are disabled by default.
+    File(file::FileWriter),
+    /// Writes to a temporary file that's renamed to the destination path when the log is
+    /// published. Colors are disabled by default.
+    AtomicFile(atomic_file::AtomicFileWriter),
+    /// Writes gzip compressed output to the file. Colors are disabled by default.
+    #[cfg(feature = "flate2")]
+    GzipFile(gzip::GzipFileWriter),
+    /// Keeps the most recent output in a fixed size in-memory buffer. See
+    /// [`crate::ring_buffer_contents()`].
+    RingBuffer(ring_buffer::RingBufferWriter),
+    /// Writes to the Web console. The default target when compiling to WebAssembly with the
+    /// `wasm-console` feature enabled.
+    #[cfg(all(target_arch = "wasm32", feature = "wasm-console"))]
+    Console(console::ConsoleWriter),
 }
 
 impl Debug for OutputTargetImpl {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.writer.fmt(f)
+    }
+}
+
+impl Debug for TargetWriter {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             #[cfg(windows)]
-            OutputTargetImpl::StderrOrWinDbg(stderr, windbg) => f
+            TargetWriter::StderrOrWinDbg(stderr, windbg) => f
                 .debug_tuple("StderrOrWinDbg")
                 .field(if stderr.supports_color() {
                     &"<stderr stream with color support>"
@@ -43,7 +88,7 @@ impl Debug for OutputTargetImpl {
                 })
                 .field(windbg)
                 .finish(),
-            OutputTargetImpl::Stderr(stderr) => f
+            TargetWriter::Stderr(stderr) => f
                 .debug_tuple("Stderr")
                 .field(if stderr.supports_color() {
                     &"<stderr stream with color support>"
@@ -52,8 +97,22 @@ impl Debug for OutputTargetImpl {
                 })
                 .finish(),
             #[cfg(windows)]
-            OutputTargetImpl::WinDbg(windbg) => f.debug_tuple("WinDbg").field(windbg).finish(),
-            OutputTargetImpl::File(file) => f.debug_tuple("File").field(file).finish(),
+            TargetWriter::WinDbg(windbg) => f.debug_tuple("WinDbg").field(windbg).finish(),
+            TargetWriter::File(file) => match file.path() {
+                Some(path) => f.debug_tuple("File").field(&path).finish(),
+                None => f.debug_tuple("File").field(&"<file handle>").finish(),
+            },
+            TargetWriter::AtomicFile(file) => {
+                f.debug_tuple("AtomicFile").field(&file.path()).finish()
+            }
+            #[cfg(feature = "flate2")]
+            TargetWriter::GzipFile(gzip) => f.debug_tuple("GzipFile").field(&gzip.path()).finish(),
+            TargetWriter::RingBuffer(ring_buffer) => f
+                .debug_tuple("RingBuffer")
+                .field(&ring_buffer.size())
+                .finish(),
+            #[cfg(all(target_arch = "wasm32", feature = "wasm-console"))]
+            TargetWriter::Console(console) => f.debug_tuple("Console").field(console).finish(),
         }
     }
 }
@@ -61,65 +120,700 @@ impl Debug for OutputTargetImpl {
 /// A simple wrapper around the `Write` and `WriteColor` traits to allow coloring text when
 /// supported by the logger target.
 pub trait WriteExt: Write {
+    /// Whether the stream supports colors and other ANSI escape sequences.
+    fn supports_color(&self) -> bool;
+
+    /// Whether colors are written as ANSI escape sequences. If this is the case, then precomputed
+    /// escape sequences can be written directly instead of going through
+    /// [`set_fg_color()`][Self::set_fg_color()]. This is not the case for Windows consoles that
+    /// may use the console API instead.
+    fn writes_ansi(&self) -> bool;
+
     /// Set the foreground text color. Doesn't do anything if the stream doesn't support colors.
-    fn set_fg_color(&mut self, color: Color);
+    /// Returns an error if the stream failed to change the color.
+    fn set_fg_color(&mut self, color: Color) -> std::io::Result<()>;
 
     /// Reset the foreground text color. Doesn't do anything if the stream doesn't support colors.
     fn reset_colors(&mut self);
 }
 
-impl WriteExt for BufferedStandardStream {
-    fn set_fg_color(&mut self, color: Color) {
-        let _ = self.set_color(ColorSpec::new().set_fg(Some(color)));
+/// A writer that's either wrapped in a [`BufWriter`], or that's written to directly when the
+/// buffer capacity is zero. This avoids going through a zero sized buffer for unbuffered writes.
+pub enum MaybeBuffered<W: Write> {
+    Buffered(BufWriter<W>),
+    Unbuffered(W),
+}
+
+impl<W: Write> MaybeBuffered<W> {
+    /// Wrap `writer` in a buffer with the given capacity. A capacity of zero means that writes go
+    /// directly to `writer`.
+    pub fn with_capacity(capacity: usize, writer: W) -> Self {
+        if capacity == 0 {
+            MaybeBuffered::Unbuffered(writer)
+        } else {
+            MaybeBuffered::Buffered(BufWriter::with_capacity(capacity, writer))
+        }
+    }
+
+    /// The buffer's capacity, or zero if the writer is unbuffered.
+    pub fn capacity(&self) -> usize {
+        match self {
+            MaybeBuffered::Buffered(writer) => writer.capacity(),
+            MaybeBuffered::Unbuffered(_) => 0,
+        }
+    }
+
+    /// Get a reference to the underlying writer.
+    pub fn get_ref(&self) -> &W {
+        match self {
+            MaybeBuffered::Buffered(writer) => writer.get_ref(),
+            MaybeBuffered::Unbuffered(writer) => writer,
+        }
+    }
+
+    /// Flush any buffered data and return the underlying writer. Buffered data is discarded if it
+    /// could not be written.
+    pub fn into_inner(self) -> W {
+        match self {
+            MaybeBuffered::Buffered(mut writer) => {
+                let _ = writer.flush();
+                writer.into_parts().0
+            }
+            MaybeBuffered::Unbuffered(writer) => writer,
+        }
+    }
+}
+
+impl<W: Write> Write for MaybeBuffered<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            MaybeBuffered::Buffered(writer) => writer.write(buf),
+            MaybeBuffered::Unbuffered(writer) => writer.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            MaybeBuffered::Buffered(writer) => writer.flush(),
+            MaybeBuffered::Unbuffered(writer) => writer.flush(),
+        }
+    }
+}
+
+/// A STDERR stream that's either buffered, which is the default, or unbuffered. See
+/// [`OutputTargetImpl::unbuffered()`]. The raw variant writes to [`std::io::stderr()`] without
+/// going through `termcolor`, and never uses colors.
+pub enum StderrStream {
+    Buffered(BufferedStandardStream),
+    Unbuffered(StandardStream),
+    Raw(MaybeBuffered<std::io::Stderr>),
+}
+
+impl StderrStream {
+    /// Create a new STDERR stream with the same buffering behavior as this one.
+    fn with_color_choice(&self, color_choice: ColorChoice) -> Self {
+        match self {
+            StderrStream::Buffered(_) => {
+                StderrStream::Buffered(BufferedStandardStream::stderr(color_choice))
+            }
+            StderrStream::Unbuffered(_) => {
+                StderrStream::Unbuffered(StandardStream::stderr(color_choice))
+            }
+            // The whole point of this variant is to skip the color handling
+            StderrStream::Raw(stream) => StderrStream::Raw(MaybeBuffered::with_capacity(
+                stream.capacity(),
+                std::io::stderr(),
+            )),
+        }
+    }
+
+    /// Replace a buffered stream with an unbuffered one with the same color support.
+    fn unbuffered(self) -> Self {
+        match self {
+            StderrStream::Buffered(stream) => {
+                StderrStream::Unbuffered(StandardStream::stderr(if stream.supports_color() {
+                    ColorChoice::Always
+                } else {
+                    ColorChoice::Never
+                }))
+            }
+            StderrStream::Unbuffered(stream) => StderrStream::Unbuffered(stream),
+            StderrStream::Raw(stream) => {
+                StderrStream::Raw(MaybeBuffered::Unbuffered(stream.into_inner()))
+            }
+        }
+    }
+}
+
+impl Write for StderrStream {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            StderrStream::Buffered(stream) => stream.write(buf),
+            StderrStream::Unbuffered(stream) => stream.write(buf),
+            StderrStream::Raw(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            StderrStream::Buffered(stream) => stream.flush(),
+            StderrStream::Unbuffered(stream) => stream.flush(),
+            StderrStream::Raw(stream) => stream.flush(),
+        }
+    }
+}
+
+impl WriteExt for StderrStream {
+    fn supports_color(&self) -> bool {
+        match self {
+            StderrStream::Buffered(stream) => WriteColor::supports_color(stream),
+            StderrStream::Unbuffered(stream) => WriteColor::supports_color(stream),
+            StderrStream::Raw(_) => false,
+        }
+    }
+
+    fn writes_ansi(&self) -> bool {
+        cfg!(not(windows)) && self.supports_color()
+    }
+
+    fn set_fg_color(&mut self, color: Color) -> std::io::Result<()> {
+        let spec = ColorSpec::new().set_fg(Some(color)).clone();
+        match self {
+            StderrStream::Buffered(stream) => stream.set_color(&spec),
+            StderrStream::Unbuffered(stream) => stream.set_color(&spec),
+            StderrStream::Raw(_) => Ok(()),
+        }
     }
 
     fn reset_colors(&mut self) {
-        let _ = self.reset();
+        let _ = match self {
+            StderrStream::Buffered(stream) => stream.reset(),
+            StderrStream::Unbuffered(stream) => stream.reset(),
+            StderrStream::Raw(_) => Ok(()),
+        };
     }
 }
 
 #[cfg(windows)]
 impl WriteExt for windbg::WinDbgWriter {
-    fn set_fg_color(&mut self, _color: Color) {}
+    fn supports_color(&self) -> bool {
+        self.colors()
+    }
+
+    fn writes_ansi(&self) -> bool {
+        self.colors()
+    }
+
+    fn set_fg_color(&mut self, color: Color) -> std::io::Result<()> {
+        if self.colors() {
+            termcolor::Ansi::new(self).set_color(ColorSpec::new().set_fg(Some(color)))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn reset_colors(&mut self) {
+        if self.colors() {
+            let _ = termcolor::Ansi::new(self).reset();
+        }
+    }
+}
+
+impl WriteExt for ring_buffer::RingBufferWriter {
+    fn supports_color(&self) -> bool {
+        false
+    }
+
+    fn writes_ansi(&self) -> bool {
+        false
+    }
+
+    fn set_fg_color(&mut self, _color: Color) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    fn reset_colors(&mut self) {}
+}
+
+#[cfg(all(target_arch = "wasm32", feature = "wasm-console"))]
+impl WriteExt for console::ConsoleWriter {
+    fn supports_color(&self) -> bool {
+        false
+    }
+
+    fn writes_ansi(&self) -> bool {
+        false
+    }
+
+    fn set_fg_color(&mut self, _color: Color) -> std::io::Result<()> {
+        Ok(())
+    }
 
     fn reset_colors(&mut self) {}
 }
 
-impl WriteExt for BufWriter<File> {
-    fn set_fg_color(&mut self, _color: Color) {}
+/// Used to format records into memory, for instance for [`crate::format_preview()`]. Colors are
+/// never written.
+impl WriteExt for Vec<u8> {
+    fn supports_color(&self) -> bool {
+        false
+    }
+
+    fn writes_ansi(&self) -> bool {
+        false
+    }
+
+    fn set_fg_color(&mut self, _color: Color) -> std::io::Result<()> {
+        Ok(())
+    }
 
     fn reset_colors(&mut self) {}
 }
 
+/// A wrapper around a [`WriteExt`] that keeps track of the number of bytes successfully written to
+/// it, and of the number of characters those bytes contain.
+pub struct CountingWriter<'a> {
+    inner: &'a mut dyn WriteExt,
+    /// The number of bytes written to `inner` so far.
+    pub bytes_written: u64,
+    /// The number of UTF-8 encoded characters in `bytes_written`.
+    chars_written: u64,
+    /// The number of characters in `chars_written` that were written through
+    /// [`write_escape_sequence()`][Self::write_escape_sequence()]. These don't take up any space
+    /// on the screen.
+    escape_chars: u64,
+    /// Set when changing the color failed. Colors are no longer used for the rest of the record
+    /// after that so the text doesn't end up in a partially colored state.
+    colors_failed: bool,
+}
+
+impl<'a> CountingWriter<'a> {
+    pub fn new(inner: &'a mut dyn WriteExt) -> Self {
+        Self {
+            inner,
+            bytes_written: 0,
+            chars_written: 0,
+            escape_chars: 0,
+            colors_failed: false,
+        }
+    }
+
+    /// Write an escape sequence. These characters are counted separately so the visible width of
+    /// the written text can still be determined.
+    pub fn write_escape_sequence(&mut self, args: std::fmt::Arguments) -> std::io::Result<()> {
+        let chars_written_before = self.chars_written;
+        let result = self.write_fmt(args);
+        self.escape_chars += self.chars_written - chars_written_before;
+
+        result
+    }
+
+    /// Write text containing escape sequences. `visible_chars` is the number of characters in
+    /// `bytes` that are not part of an escape sequence.
+    pub fn write_with_escapes(
+        &mut self,
+        bytes: &[u8],
+        visible_chars: usize,
+    ) -> std::io::Result<()> {
+        let chars_written_before = self.chars_written;
+        let result = self.write_all(bytes);
+        self.escape_chars +=
+            (self.chars_written - chars_written_before).saturating_sub(visible_chars as u64);
+
+        result
+    }
+
+    /// The number of characters written so far, excluding escape sequences. This is used as the
+    /// number of columns the written text takes up, so wide characters count as a single column.
+    /// Color escape sequences written through [`WriteExt::set_fg_color()`] are never counted.
+    pub fn visible_chars_written(&self) -> u64 {
+        self.chars_written - self.escape_chars
+    }
+}
+
+impl Write for CountingWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let bytes_written = self.inner.write(buf)?;
+        self.bytes_written += bytes_written as u64;
+        // Every byte that's not a UTF-8 continuation byte starts a new character. This also works
+        // when a character is split over multiple writes.
+        self.chars_written += buf[..bytes_written]
+            .iter()
+            .filter(|byte| (**byte & 0b1100_0000) != 0b1000_0000)
+            .count() as u64;
+
+        Ok(bytes_written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl WriteExt for CountingWriter<'_> {
+    fn supports_color(&self) -> bool {
+        !self.colors_failed && self.inner.supports_color()
+    }
+
+    fn writes_ansi(&self) -> bool {
+        !self.colors_failed && self.inner.writes_ansi()
+    }
+
+    fn set_fg_color(&mut self, color: Color) -> std::io::Result<()> {
+        if self.colors_failed {
+            return Ok(());
+        }
+
+        let result = self.inner.set_fg_color(color);
+        if result.is_err() {
+            // Try to get back to a known state and write the rest of the record as plain text
+            self.colors_failed = true;
+            self.inner.reset_colors();
+        }
+
+        result
+    }
+
+    fn reset_colors(&mut self) {
+        if !self.colors_failed {
+            self.inner.reset_colors()
+        }
+    }
+}
+
 impl OutputTargetImpl {
+    fn new(writer: TargetWriter) -> Self {
+        Self {
+            writer,
+            unflushed_bytes: 0,
+        }
+    }
+
     /// Construct an [`OutputTargetImpl`] that writes to STDERR with optional color support
     /// determined by the environment. If a Windows debugger is attached when writing debug output,
     /// then the output is sent to the Windows debugger instead.
     #[cfg(windows)]
     pub fn new_stderr_or_windbg() -> Self {
-        OutputTargetImpl::StderrOrWinDbg(
-            BufferedStandardStream::stderr(stderr_color_support()),
+        Self::new(TargetWriter::StderrOrWinDbg(
+            StderrStream::Buffered(BufferedStandardStream::stderr(stderr_color_support())),
             windbg::WinDbgWriter::default(),
-        )
+        ))
     }
 
     /// Construct an [`OutputTargetImpl`] that writes to STDERR with optional color support
     /// determined by the environment.
     pub fn new_stderr() -> Self {
-        OutputTargetImpl::Stderr(BufferedStandardStream::stderr(stderr_color_support()))
+        Self::new(TargetWriter::Stderr(StderrStream::Buffered(
+            BufferedStandardStream::stderr(stderr_color_support()),
+        )))
+    }
+
+    /// Construct an [`OutputTargetImpl`] that writes to STDERR through [`std::io::stderr()`]
+    /// without any color support.
+    pub fn new_raw_stderr() -> Self {
+        Self::new(TargetWriter::Stderr(StderrStream::Raw(
+            MaybeBuffered::Buffered(BufWriter::new(std::io::stderr())),
+        )))
     }
 
     /// Construct an [`OutputTargetImpl`] that writes to the Windows debugger.
     #[cfg(windows)]
     pub fn new_windbg() -> Self {
-        OutputTargetImpl::WinDbg(windbg::WinDbgWriter::default())
+        Self::new(TargetWriter::WinDbg(windbg::WinDbgWriter::default()))
+    }
+
+    /// Construct an [`OutputTargetImpl`] that keeps the last `size` bytes of output in memory.
+    pub fn new_ring_buffer(size: usize) -> Self {
+        Self::new(TargetWriter::RingBuffer(
+            ring_buffer::RingBufferWriter::new(size),
+        ))
+    }
+
+    /// Construct an [`OutputTargetImpl`] that writes to the Web console.
+    #[cfg(all(target_arch = "wasm32", feature = "wasm-console"))]
+    pub fn new_console() -> Self {
+        Self::new(TargetWriter::Console(console::ConsoleWriter::default()))
+    }
+
+    /// Construct an [`OutputTargetImpl`] for doing buffered writes to a file. If `create_dirs` is
+    /// set, then the file's parent directories are created if they don't exist yet.
+    pub fn new_file_path<P: AsRef<Path>>(
+        path: P,
+        create_dirs: bool,
+    ) -> Result<Self, std::io::Error> {
+        let path = path.as_ref();
+        let file = open_log_file(path, create_dirs)?;
+
+        Ok(Self::new(TargetWriter::File(file::FileWriter::new(
+            file,
+            path.to_owned(),
+        ))))
+    }
+
+    /// Construct an [`OutputTargetImpl`] for doing buffered writes to a file handle that was opened
+    /// elsewhere. The handle is never closed or reopened by the logger.
+    pub fn new_file(file: File) -> Self {
+        Self::new(TargetWriter::File(file::FileWriter::from_handle(file)))
+    }
+
+    /// Construct an [`OutputTargetImpl`] that writes to a temporary file next to `path`, and that
+    /// renames that file to `path` when [`finish_files()`][Self::finish_files()] is called. See
+    /// [`new_file_path()`][Self::new_file_path()].
+    pub fn new_atomic_file_path<P: AsRef<Path>>(
+        path: P,
+        create_dirs: bool,
+    ) -> Result<Self, std::io::Error> {
+        let path = path.as_ref();
+        if create_dirs {
+            create_parent_dirs(path)?;
+        }
+
+        Ok(Self::new(TargetWriter::AtomicFile(
+            atomic_file::AtomicFileWriter::new(path.to_owned())?,
+        )))
+    }
+
+    /// Construct an [`OutputTargetImpl`] that writes gzip compressed output to a file. See
+    /// [`new_file_path()`][Self::new_file_path()].
+    #[cfg(feature = "flate2")]
+    pub fn new_gzip_file_path<P: AsRef<Path>>(
+        path: P,
+        create_dirs: bool,
+    ) -> Result<Self, std::io::Error> {
+        let path = path.as_ref();
+        let file = open_log_file(path, create_dirs)?;
+
+        Ok(Self::new(TargetWriter::GzipFile(
+            gzip::GzipFileWriter::new(file, path.to_owned()),
+        )))
+    }
+
+    /// Construct an [`OutputTargetImpl`] for a file that's truncated instead of growing beyond
+    /// `max_bytes`. See [`new_file_path()`][Self::new_file_path()].
+    pub fn new_capped_file_path<P: AsRef<Path>>(
+        path: P,
+        max_bytes: u64,
+        create_dirs: bool,
+    ) -> Result<Self, std::io::Error> {
+        let mut target = Self::new_file_path(path, create_dirs)?;
+        if let TargetWriter::File(file) = &mut target.writer {
+            file.set_max_bytes(max_bytes);
+        }
+
+        Ok(target)
     }
 
-    /// Construct an [`OutputTargetImpl`] for doing buffered writes to a file.
-    pub fn new_file_path<P: AsRef<Path>>(path: P) -> Result<Self, std::io::Error> {
-        let file = File::options().create(true).append(true).open(path)?;
+    /// Override the target's default color behavior. With [`ColorMode::Auto`] the target keeps its
+    /// default behavior, which means that STDERR streams use colors depending on the environment
+    /// and files and the Windows debugger never use colors. For `StderrOrWinDbg` the mode is
+    /// applied to the STDERR stream and the Windows debugger separately, so the debugger's colors
+    /// don't depend on whether STDERR is a terminal. The Web console never uses colors.
+    pub fn with_color_mode(self, mode: ColorMode) -> Self {
+        let color_choice = match mode {
+            ColorMode::Auto => return self,
+            ColorMode::Always => ColorChoice::Always,
+            ColorMode::Never => ColorChoice::Never,
+        };
+
+        let writer = match self.writer {
+            #[cfg(windows)]
+            TargetWriter::StderrOrWinDbg(stderr, mut windbg) => {
+                windbg.set_colors(color_choice == ColorChoice::Always);
+                TargetWriter::StderrOrWinDbg(stderr.with_color_choice(color_choice), windbg)
+            }
+            TargetWriter::Stderr(stderr) => {
+                TargetWriter::Stderr(stderr.with_color_choice(color_choice))
+            }
+            #[cfg(windows)]
+            TargetWriter::WinDbg(mut windbg) => {
+                windbg.set_colors(color_choice == ColorChoice::Always);
+                TargetWriter::WinDbg(windbg)
+            }
+            TargetWriter::File(mut file) => {
+                file.set_colors(color_choice == ColorChoice::Always);
+                TargetWriter::File(file)
+            }
+            TargetWriter::AtomicFile(mut file) => {
+                file.set_colors(color_choice == ColorChoice::Always);
+                TargetWriter::AtomicFile(file)
+            }
+            #[cfg(feature = "flate2")]
+            TargetWriter::GzipFile(mut gzip) => {
+                gzip.set_colors(color_choice == ColorChoice::Always);
+                TargetWriter::GzipFile(gzip)
+            }
+            TargetWriter::RingBuffer(ring_buffer) => TargetWriter::RingBuffer(ring_buffer),
+            #[cfg(all(target_arch = "wasm32", feature = "wasm-console"))]
+            TargetWriter::Console(console) => TargetWriter::Console(console),
+        };
+
+        Self { writer, ..self }
+    }
+
+    /// Don't buffer writes to STDERR or to files. Every write is then sent to the underlying stream
+    /// immediately. The Windows debugger is still line buffered since `OutputDebugString()` needs
+    /// to be called with whole lines, and the Web console writes every record as a single message.
+    /// Gzip compressed files are always buffered, since compression needs to be able to look at
+    /// more than a single record.
+    pub fn unbuffered(self) -> Self {
+        let writer = match self.writer {
+            #[cfg(windows)]
+            TargetWriter::StderrOrWinDbg(stderr, windbg) => {
+                TargetWriter::StderrOrWinDbg(stderr.unbuffered(), windbg)
+            }
+            TargetWriter::Stderr(stderr) => TargetWriter::Stderr(stderr.unbuffered()),
+            #[cfg(windows)]
+            TargetWriter::WinDbg(windbg) => TargetWriter::WinDbg(windbg),
+            TargetWriter::File(file) => TargetWriter::File(file.unbuffered()),
+            TargetWriter::AtomicFile(file) => {
+                TargetWriter::AtomicFile(file.with_buffer_capacity(0))
+            }
+            #[cfg(feature = "flate2")]
+            TargetWriter::GzipFile(gzip) => TargetWriter::GzipFile(gzip),
+            TargetWriter::RingBuffer(ring_buffer) => TargetWriter::RingBuffer(ring_buffer),
+            #[cfg(all(target_arch = "wasm32", feature = "wasm-console"))]
+            TargetWriter::Console(console) => TargetWriter::Console(console),
+        };
 
-        Ok(Self::File(BufWriter::new(file)))
+        Self { writer, ..self }
+    }
+
+    /// Set the level of the record that's about to be written. The Web console uses this to decide
+    /// between `console.log()` and `console.error()`. Does nothing for other targets.
+    pub fn set_record_level(&mut self, level: Level) {
+        #[cfg(all(target_arch = "wasm32", feature = "wasm-console"))]
+        if let TargetWriter::Console(console) = &mut self.writer {
+            console.set_level(level);
+        }
+        #[cfg(not(all(target_arch = "wasm32", feature = "wasm-console")))]
+        let _ = level;
+    }
+
+    /// Change the size of the write buffer for files and for [`StderrStream::Raw`]. A capacity of
+    /// zero means writes are not buffered at all, like with [`unbuffered()`][Self::unbuffered()].
+    /// The termcolor based STDERR streams, the Windows debugger, and gzip compressed files keep
+    /// their own buffering. This should only be called before anything is written to the target.
+    pub fn with_buffer_capacity(self, capacity: usize) -> Self {
+        let writer = match self.writer {
+            TargetWriter::Stderr(StderrStream::Raw(stream)) => TargetWriter::Stderr(
+                StderrStream::Raw(MaybeBuffered::with_capacity(capacity, stream.into_inner())),
+            ),
+            TargetWriter::File(file) => TargetWriter::File(file.with_buffer_capacity(capacity)),
+            TargetWriter::AtomicFile(file) => {
+                TargetWriter::AtomicFile(file.with_buffer_capacity(capacity))
+            }
+            writer => writer,
+        };
+
+        Self { writer, ..self }
+    }
+
+    /// Write a UTF-8 byte order mark if this target writes to a file that was empty when it was
+    /// opened. Does nothing for other targets.
+    pub fn write_utf8_bom(&mut self) {
+        let _ = match &mut self.writer {
+            TargetWriter::File(file) => file.write_utf8_bom(),
+            TargetWriter::AtomicFile(file) => file.write_utf8_bom(),
+            _ => Ok(()),
+        };
+    }
+
+    /// Flush and reopen the file if this target writes to a file. For gzip compressed files this
+    /// finishes the current gzip member, and a new member is started in the reopened file on the
+    /// next write. Does nothing for other targets.
+    pub fn reopen_file(&mut self) -> std::io::Result<()> {
+        match &mut self.writer {
+            TargetWriter::File(file) => file.reopen(),
+            #[cfg(feature = "flate2")]
+            TargetWriter::GzipFile(gzip) => gzip.finish(),
+            _ => Ok(()),
+        }
+    }
+
+    /// Flush all buffered output. Unlike flushing the [`writer()`][Self::writer()], which the
+    /// logger does after every record, this also emits a sync point for gzip compressed files so
+    /// everything written so far ends up in the file.
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        self.unflushed_bytes = 0;
+        match &mut self.writer {
+            #[cfg(feature = "flate2")]
+            TargetWriter::GzipFile(gzip) => gzip.sync(),
+            _ => self.writer().flush(),
+        }
+    }
+
+    /// Flush the writer after a record containing `len` bytes has been written to it. If a
+    /// `threshold` is set, then the writer is only flushed once more than `threshold` bytes have
+    /// been written since it was last flushed, or when `force` is set. A forced flush also emits a
+    /// sync point for gzip compressed files, since it may be followed by a crash. The Web console
+    /// is always flushed since it writes every flush as a separate message.
+    pub fn flush_after_record(&mut self, len: u64, threshold: Option<u64>, force: bool) {
+        self.unflushed_bytes += len;
+        let should_flush = match threshold {
+            Some(threshold) => force || self.unflushed_bytes > threshold,
+            None => true,
+        };
+        #[cfg(all(target_arch = "wasm32", feature = "wasm-console"))]
+        let should_flush = should_flush || matches!(self.writer, TargetWriter::Console(_));
+
+        if should_flush {
+            let _ = if force {
+                self.flush()
+            } else {
+                self.writer().flush()
+            };
+            self.unflushed_bytes = 0;
+        }
+    }
+
+    /// Finish writing the file if this target writes to a file that needs to be finalized. Gzip
+    /// compressed files are finished by writing the gzip trailer, and atomic files are renamed to
+    /// their destination path. Does nothing for other targets.
+    pub fn finish_files(&mut self) -> std::io::Result<()> {
+        match &mut self.writer {
+            TargetWriter::AtomicFile(file) => file.publish(),
+            #[cfg(feature = "flate2")]
+            TargetWriter::GzipFile(gzip) => gzip.finish(),
+            _ => Ok(()),
+        }
+    }
+
+    /// Flush and close the file if this target writes to a file. The file is opened again in
+    /// append mode the next time something is written to it. Gzip compressed files are finished,
+    /// and atomic files are closed without publishing them. Does nothing for other targets.
+    pub fn close_file(&mut self) -> std::io::Result<()> {
+        self.unflushed_bytes = 0;
+        match &mut self.writer {
+            TargetWriter::File(file) => file.close(),
+            TargetWriter::AtomicFile(file) => file.close(),
+            #[cfg(feature = "flate2")]
+            TargetWriter::GzipFile(gzip) => gzip.finish(),
+            _ => Ok(()),
+        }
+    }
+
+    /// Whether this target writes to a file. This includes atomic and gzip compressed files.
+    pub fn is_file(&self) -> bool {
+        match self.writer {
+            TargetWriter::File(_) | TargetWriter::AtomicFile(_) => true,
+            #[cfg(feature = "flate2")]
+            TargetWriter::GzipFile(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Whether this target writes to STDERR without going through `termcolor`. See
+    /// [`StderrStream::Raw`].
+    pub fn is_raw_stderr(&self) -> bool {
+        matches!(self.writer, TargetWriter::Stderr(StderrStream::Raw(_)))
+    }
+
+    /// The contents of the ring buffer if this is a ring buffer target, from oldest to newest.
+    pub fn ring_buffer_contents(&self) -> Option<Vec<u8>> {
+        match &self.writer {
+            TargetWriter::RingBuffer(ring_buffer) => Some(ring_buffer.contents()),
+            _ => None,
+        }
     }
 
     /// Returns a writer that can be written to using the [`write!()`] and [`writeln!()`] macros.
@@ -129,50 +823,125 @@ impl OutputTargetImpl {
     ///
     /// Needs to be a single function since otherwise you'd need to borrow from this struct twice.
     pub fn writer(&mut self) -> &mut dyn WriteExt {
-        match self {
+        match self.writer {
             #[cfg(windows)]
-            OutputTargetImpl::StderrOrWinDbg(_, ref mut windbg) if windbg::attached() => windbg,
+            TargetWriter::StderrOrWinDbg(_, ref mut windbg) if windbg.attached() => windbg,
             #[cfg(windows)]
-            OutputTargetImpl::StderrOrWinDbg(ref mut stderr, _) => stderr,
-            OutputTargetImpl::Stderr(ref mut stderr) => stderr,
+            TargetWriter::StderrOrWinDbg(ref mut stderr, _) => stderr,
+            TargetWriter::Stderr(ref mut stderr) => stderr,
             #[cfg(windows)]
-            OutputTargetImpl::WinDbg(ref mut windbg) => windbg,
-            OutputTargetImpl::File(ref mut file) => file,
+            TargetWriter::WinDbg(ref mut windbg) => windbg,
+            TargetWriter::File(ref mut file) => file,
+            TargetWriter::AtomicFile(ref mut file) => file,
+            #[cfg(feature = "flate2")]
+            TargetWriter::GzipFile(ref mut gzip) => gzip,
+            TargetWriter::RingBuffer(ref mut ring_buffer) => ring_buffer,
+            #[cfg(all(target_arch = "wasm32", feature = "wasm-console"))]
+            TargetWriter::Console(ref mut console) => console,
         }
     }
 
     /// If the `NIH_LOG` environment variable is set, then parse that according to the rules defined
-    /// in the project's readme. Otherwise defaults to the dynamic `StderrOrWinDbg` target. If
+    /// in the project's readme. Otherwise defaults to the dynamic `StderrOrWinDbg` target, or to the
+    /// Web console when compiling to WebAssembly with the `wasm-console` feature enabled. If
     /// `NIH_LOG` is set to output to a file and the file couldn't be opened, then this will write
-    /// the error to STDERR and then also fall back to `StderrOrWinDbg`.
-    pub fn default_from_environment() -> Self {
-        let nih_log_env = std::env::var(NIH_LOG_ENV);
-        let nih_log_env_str = nih_log_env.as_deref().unwrap_or("");
-        if nih_log_env_str.eq_ignore_ascii_case("stderr") {
-            return Self::new_stderr();
+    /// the error to STDERR the first time this happens and then also fall back to `StderrOrWinDbg`.
+    /// If `create_dirs` is set, then the file's parent directories are created if needed. The
+    /// environment is only parsed once, but the file is opened again every time this is called.
+    pub fn default_from_environment(create_dirs: bool) -> Self {
+        let (target, warnings) =
+            Self::from_cached_env_config(EnvConfig::cached(), create_dirs, &ENV_WARNINGS_PRINTED);
+
+        // This is used when the logger itself can't be used, so these can't be deferred
+        for warning in warnings {
+            eprintln!("{warning}");
         }
-        #[cfg(windows)]
-        if nih_log_env_str.eq_ignore_ascii_case("windbg") {
-            return Self::new_windbg();
+
+        target
+    }
+
+    /// Create the target for `config` without modifying it. Also returns the new problems that
+    /// occurred while opening the target, but only if `warnings_printed` was not yet set. The flag
+    /// is set when any problems are returned.
+    fn from_cached_env_config(
+        config: &EnvConfig,
+        create_dirs: bool,
+        warnings_printed: &AtomicBool,
+    ) -> (Self, Vec<String>) {
+        let mut config = config.clone();
+        let num_previous_warnings = config.warnings.len();
+        let target = Self::from_env_config(&mut config, create_dirs);
+
+        let mut warnings = config.warnings.split_off(num_previous_warnings);
+        if !warnings.is_empty() && warnings_printed.swap(true, Ordering::Relaxed) {
+            warnings.clear();
         }
-        if !nih_log_env_str.is_empty() {
-            match Self::new_file_path(nih_log_env_str) {
+
+        (target, warnings)
+    }
+
+    /// The same as [`default_from_environment()`][Self::default_from_environment()], but using an
+    /// already parsed `NIH_LOG` configuration. If the file could not be opened, then this is
+    /// recorded in the configuration's warnings instead of being printed to STDERR.
+    pub fn from_env_config(config: &mut EnvConfig, create_dirs: bool) -> Self {
+        match &config.target {
+            EnvTarget::Default => (),
+            EnvTarget::Stderr => return Self::new_stderr(),
+            #[cfg(windows)]
+            EnvTarget::WinDbg => return Self::new_windbg(),
+            EnvTarget::File(path) => match Self::new_file_path(path, create_dirs) {
                 Ok(target) => return target,
-                // TODO: Print this using the actual logger
-                Err(err) => eprintln!(
-                    "Could not open '{nih_log_env_str}' from NIH_LOG for logging, falling back to \
-                     STDERR: {err}"
-                ),
-            }
+                Err(err) => {
+                    let warning = format!(
+                        "Could not open '{}' from NIH_LOG for logging, falling back to STDERR: {err}",
+                        path.display()
+                    );
+                    config.warn(warning);
+                }
+            },
         }
 
         #[cfg(windows)]
         return Self::new_stderr_or_windbg();
-        #[cfg(not(windows))]
+        // There are no environment variables or standard IO streams in the browser, so the Web
+        // console is the only sensible default there
+        #[cfg(all(target_arch = "wasm32", feature = "wasm-console"))]
+        return Self::new_console();
+        #[cfg(not(any(windows, all(target_arch = "wasm32", feature = "wasm-console"))))]
         return Self::new_stderr();
     }
 }
 
+/// Whether a Windows debugger is currently attached to the process. Always `false` on other
+/// platforms.
+pub fn debugger_attached() -> bool {
+    #[cfg(windows)]
+    return windbg::debugger_present();
+    #[cfg(not(windows))]
+    return false;
+}
+
+/// Open a log file for appending, creating it if it doesn't exist yet. If `create_dirs` is set, then
+/// the file's parent directories are created if they don't exist yet.
+fn open_log_file(path: &Path, create_dirs: bool) -> Result<File, std::io::Error> {
+    if create_dirs {
+        create_parent_dirs(path)?;
+    }
+
+    File::options().create(true).append(true).open(path)
+}
+
+/// Create a file's parent directories if they don't exist yet.
+fn create_parent_dirs(path: &Path) -> Result<(), std::io::Error> {
+    match path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        Some(parent) => std::fs::create_dir_all(parent),
+        None => Ok(()),
+    }
+}
+
 /// Whether to use colors when outputting to STDERR. Considers the `CLICOLOR`, `CLICOLOR_FORCE`, and
 /// `NO_COLOR` environment variables, and whether or not STDERR is attached to a real TTY.
 fn stderr_color_support() -> ColorChoice {
@@ -202,3 +971,106 @@ fn stderr_color_support() -> ColorChoice {
         ColorChoice::Never
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// A path in the temporary directory that's unique to this process and test. Any existing file
+    /// at that path is removed first.
+    pub(super) fn temp_log_path(name: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("nih-log-test-{}-{name}.log", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        path
+    }
+
+    #[test]
+    fn zero_capacity_writes_immediately() {
+        let path = temp_log_path("zero-capacity");
+        let mut target = OutputTargetImpl::new_file_path(&path, false)
+            .unwrap()
+            .with_buffer_capacity(0);
+
+        target.writer().write_all(b"a record\n").unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"a record\n");
+
+        drop(target);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn nonzero_capacity_buffers_writes() {
+        let path = temp_log_path("nonzero-capacity");
+        let mut target = OutputTargetImpl::new_file_path(&path, false)
+            .unwrap()
+            .with_buffer_capacity(1024);
+
+        target.writer().write_all(b"a record\n").unwrap();
+        assert!(std::fs::read(&path).unwrap().is_empty());
+
+        target.writer().flush().unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"a record\n");
+
+        drop(target);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[cfg(feature = "flate2")]
+    #[test]
+    fn forced_flush_syncs_gzip_files() {
+        let path = temp_log_path("gzip-forced-flush");
+        let mut target = OutputTargetImpl::new_gzip_file_path(&path, false).unwrap();
+
+        target.writer().write_all(b"an error\n").unwrap();
+        target.flush_after_record(9, Some(1024), true);
+
+        // The stream has not been finished yet, so decoding stops with an error after the synced
+        // data
+        let mut decoder = flate2::read::GzDecoder::new(File::open(&path).unwrap());
+        let mut decoded = Vec::new();
+        let mut buffer = [0; 64];
+        while let Ok(len @ 1..) = std::io::Read::read(&mut decoder, &mut buffer) {
+            decoded.extend_from_slice(&buffer[..len]);
+        }
+        assert_eq!(decoded, b"an error\n");
+
+        drop(target);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn env_file_open_failure_reported_once() {
+        let config = EnvConfig {
+            raw_value: None,
+            config_file: None,
+            target: EnvTarget::File(temp_log_path("missing-dir").join("nih-log.log")),
+            level: None,
+            warnings: vec![String::from("a warning from parsing the configuration")],
+        };
+        let warnings_printed = AtomicBool::new(false);
+
+        let (target, warnings) =
+            OutputTargetImpl::from_cached_env_config(&config, false, &warnings_printed);
+        assert!(!target.is_file());
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].starts_with("Could not open"));
+
+        let (target, warnings) =
+            OutputTargetImpl::from_cached_env_config(&config, false, &warnings_printed);
+        assert!(!target.is_file());
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn zero_capacity_is_unbuffered() {
+        assert_eq!(MaybeBuffered::with_capacity(0, Vec::new()).capacity(), 0);
+        assert!(matches!(
+            MaybeBuffered::with_capacity(0, Vec::new()),
+            MaybeBuffered::Unbuffered(_)
+        ));
+        assert_eq!(MaybeBuffered::with_capacity(16, Vec::new()).capacity(), 16);
+    }
+}
@@ -0,0 +1,236 @@
+//! The writer for log files that are only published once the log is complete.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use termcolor::{Ansi, Color, ColorSpec, WriteColor};
+
+use super::{MaybeBuffered, WriteExt};
+
+/// A writer that writes the log to a temporary file next to the destination path, and that
+/// renames the temporary file to the destination path when the log is published. Readers thus
+/// never see a partially written log. The destination file is replaced if it already exists.
+///
+/// Anything written after the log has been published is appended to the published file directly.
+/// If the writer is dropped before the log has been published, then the temporary file is removed
+/// and the destination file is left untouched.
+pub struct AtomicFileWriter {
+    /// The path the log is published to.
+    path: PathBuf,
+    /// The path of the temporary file the log is written to until it's published.
+    temp_path: PathBuf,
+    /// The open file. This is `None` after the log has been published or the file has been closed,
+    /// until something else is written.
+    writer: Option<MaybeBuffered<File>>,
+    /// The write buffer's capacity. Needed to reopen the file after the log has been published.
+    capacity: usize,
+    /// Whether the temporary file has been renamed to `path`.
+    published: bool,
+    /// Whether ANSI color escape sequences should be written to the file.
+    colors: bool,
+}
+
+impl AtomicFileWriter {
+    /// Create the temporary file for `path`. An existing temporary file from a previous run is
+    /// truncated.
+    pub fn new(path: PathBuf) -> std::io::Result<Self> {
+        let temp_path = temp_path(&path)?;
+        let file = File::create(&temp_path)?;
+        let writer = BufWriter::new(file);
+
+        Ok(Self {
+            path,
+            temp_path,
+            capacity: writer.capacity(),
+            writer: Some(MaybeBuffered::Buffered(writer)),
+            published: false,
+            colors: false,
+        })
+    }
+
+    /// The path the log is published to.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Change the size of the write buffer. A capacity of zero means that every write is sent to
+    /// the file immediately. This should only be called before anything is written to the file.
+    pub fn with_buffer_capacity(self, capacity: usize) -> Self {
+        let mut this = self;
+        if let Some(writer) = this.writer.take() {
+            this.writer = Some(MaybeBuffered::with_capacity(capacity, writer.into_inner()));
+        }
+        this.capacity = capacity;
+
+        this
+    }
+
+    /// Enable or disable writing ANSI color escape sequences.
+    pub fn set_colors(&mut self, colors: bool) {
+        self.colors = colors;
+    }
+
+    /// Write a UTF-8 byte order mark. The temporary file is always empty when it's created, so
+    /// this should only be called before anything else is written to the file.
+    pub fn write_utf8_bom(&mut self) -> std::io::Result<()> {
+        if !self.published {
+            self.write_all("\u{feff}".as_bytes())?;
+            self.flush()?;
+        }
+
+        Ok(())
+    }
+
+    /// Flush and close the temporary file and rename it to the destination path. If the log has
+    /// already been published, then this only flushes the file.
+    pub fn publish(&mut self) -> std::io::Result<()> {
+        if self.published {
+            return self.flush();
+        }
+
+        // The file needs to be closed before it can be renamed on Windows
+        if let Some(mut writer) = self.writer.take() {
+            writer.flush()?;
+        }
+        std::fs::rename(&self.temp_path, &self.path)?;
+        self.published = true;
+
+        Ok(())
+    }
+
+    /// Flush and close the current file without publishing the log. The file is opened again the
+    /// next time something is written to it.
+    pub fn close(&mut self) -> std::io::Result<()> {
+        match self.writer.take() {
+            Some(mut writer) => writer.flush(),
+            None => Ok(()),
+        }
+    }
+
+    /// The writer for the current file. Reopens the published file for appending if the log has
+    /// already been published.
+    fn writer(&mut self) -> std::io::Result<&mut MaybeBuffered<File>> {
+        if self.writer.is_none() {
+            let path = if self.published {
+                &self.path
+            } else {
+                &self.temp_path
+            };
+            let file = File::options().create(true).append(true).open(path)?;
+            self.writer = Some(MaybeBuffered::with_capacity(self.capacity, file));
+        }
+
+        Ok(self.writer.as_mut().expect("The writer was just created"))
+    }
+}
+
+/// The temporary file used for `path`. This is in the same directory so the file can be renamed
+/// without moving it to another file system.
+fn temp_path(path: &Path) -> std::io::Result<PathBuf> {
+    let file_name = path.file_name().ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("'{}' is not a file path", path.display()),
+        )
+    })?;
+
+    let mut temp_file_name = file_name.to_owned();
+    temp_file_name.push(".tmp");
+
+    Ok(path.with_file_name(temp_file_name))
+}
+
+impl Drop for AtomicFileWriter {
+    fn drop(&mut self) {
+        // The log is only published on shutdown. Dropping the writer before that, for instance
+        // because installing the logger failed, should not replace the destination file.
+        if !self.published {
+            // The file needs to be closed before it can be removed on Windows
+            self.writer = None;
+            let _ = std::fs::remove_file(&self.temp_path);
+        }
+    }
+}
+
+impl Write for AtomicFileWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.writer()?.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match &mut self.writer {
+            Some(writer) => writer.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl WriteExt for AtomicFileWriter {
+    fn supports_color(&self) -> bool {
+        self.colors
+    }
+
+    fn writes_ansi(&self) -> bool {
+        self.colors
+    }
+
+    fn set_fg_color(&mut self, color: Color) -> std::io::Result<()> {
+        if self.colors {
+            Ansi::new(self).set_color(ColorSpec::new().set_fg(Some(color)))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn reset_colors(&mut self) {
+        if self.colors {
+            let _ = Ansi::new(self).reset();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::target::tests::temp_log_path;
+
+    #[test]
+    fn destination_untouched_until_published() {
+        let path = temp_log_path("atomic-publish");
+        std::fs::write(&path, "previous run\n").unwrap();
+
+        let mut writer = AtomicFileWriter::new(path.clone()).unwrap();
+        writer.write_all(b"a record\n").unwrap();
+        writer.flush().unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"previous run\n");
+
+        writer.publish().unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"a record\n");
+        assert!(!writer.temp_path.exists());
+
+        // Records written after publishing are appended to the published file
+        writer.write_all(b"another record\n").unwrap();
+        writer.flush().unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"a record\nanother record\n");
+
+        drop(writer);
+        assert!(path.exists());
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn dropping_unpublished_writer_removes_temp_file() {
+        let path = temp_log_path("atomic-drop");
+        std::fs::write(&path, "previous run\n").unwrap();
+
+        let mut writer = AtomicFileWriter::new(path.clone()).unwrap();
+        let temp_path = writer.temp_path.clone();
+        writer.write_all(b"a record\n").unwrap();
+        writer.flush().unwrap();
+        drop(writer);
+
+        assert_eq!(std::fs::read(&path).unwrap(), b"previous run\n");
+        assert!(!temp_path.exists());
+        let _ = std::fs::remove_file(&path);
+    }
+}
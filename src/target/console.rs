@@ -0,0 +1,81 @@
+//! An adapter for logging to the Web console when compiling to WebAssembly. Like the WinDbg
+//! adapter, this is split off into a module to avoid littering `#[cfg]` attributes all over the
+//! place.
+//!
+//! This doesn't depend on `wasm-bindgen`. Instead, the module importing the WebAssembly binary needs
+//! to provide a `nih_log` import module containing `console_log(ptr, len)` and
+//! `console_error(ptr, len)` functions. These receive a pointer into the WebAssembly memory and a
+//! length in bytes for a UTF-8 string, which they should decode and pass to `console.log()` and
+//! `console.error()` respectively.
+
+use log::Level;
+use std::io::Write;
+
+#[link(wasm_import_module = "nih_log")]
+extern "C" {
+    fn console_log(ptr: *const u8, len: usize);
+    fn console_error(ptr: *const u8, len: usize);
+}
+
+/// A writer that sends every record to the Web console as a single message. Output is buffered
+/// until [`flush()`][Write::flush()] is called, which the logger does after every record. Errors
+/// and warnings are written using `console.error()`, and everything else is written using
+/// `console.log()`.
+#[derive(Debug)]
+pub struct ConsoleWriter {
+    /// The unwritten output for the current record.
+    buffer: Vec<u8>,
+    /// The level of the record currently being written. Determines which console function is
+    /// used.
+    level: Level,
+}
+
+impl Default for ConsoleWriter {
+    fn default() -> Self {
+        Self {
+            buffer: Vec::new(),
+            level: Level::Info,
+        }
+    }
+}
+
+impl Drop for ConsoleWriter {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+impl ConsoleWriter {
+    /// Set the level of the record that's about to be written.
+    pub fn set_level(&mut self, level: Level) {
+        self.level = level;
+    }
+}
+
+impl Write for ConsoleWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        // The console already puts every message on its own line
+        let message = self.buffer.strip_suffix(b"\n").unwrap_or(&self.buffer);
+        if message.is_empty() {
+            self.buffer.clear();
+            return Ok(());
+        }
+
+        let message = String::from_utf8_lossy(message);
+        match self.level {
+            Level::Error | Level::Warn => unsafe { console_error(message.as_ptr(), message.len()) },
+            Level::Info | Level::Debug | Level::Trace => unsafe {
+                console_log(message.as_ptr(), message.len())
+            },
+        }
+        self.buffer.clear();
+
+        Ok(())
+    }
+}
@@ -0,0 +1,266 @@
+//! The writer for file based output targets.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use termcolor::{Ansi, Color, ColorSpec, WriteColor};
+
+use super::{MaybeBuffered, WriteExt};
+
+/// A buffered writer for log files. Colors are written as ANSI escape sequences if they are
+/// enabled, which is not the case by default. The file's path is kept around so the file can be
+/// reopened later.
+pub struct FileWriter {
+    /// The path the file was opened from. Used to reopen the file. This is `None` for file handles
+    /// passed to the logger directly, which are never closed or reopened.
+    path: Option<PathBuf>,
+    /// The open file. This is `None` after the file has been closed, until something else is
+    /// written.
+    writer: Option<MaybeBuffered<File>>,
+    /// The write buffer's capacity. Needed to reopen the file after it has been closed.
+    capacity: usize,
+    /// Whether ANSI color escape sequences should be written to the file.
+    colors: bool,
+    /// Whether the file was empty when it was opened. Used to determine whether a byte order mark
+    /// should be written.
+    was_empty: bool,
+    /// If set, the file is truncated before a new line is written when that would cause the
+    /// file to grow beyond this size.
+    max_bytes: Option<u64>,
+    /// The current size of the file in bytes, including buffered data.
+    len: u64,
+    /// Whether the last byte written was a line feed. Files are only truncated at the start of a
+    /// line so records are never cut in half.
+    at_line_start: bool,
+}
+
+impl FileWriter {
+    pub fn new(file: File, path: PathBuf) -> Self {
+        Self::new_with_path(file, Some(path))
+    }
+
+    /// Wrap a file handle that was opened elsewhere. The file may not have a path, so it's kept
+    /// open until the writer is dropped.
+    pub fn from_handle(file: File) -> Self {
+        Self::new_with_path(file, None)
+    }
+
+    fn new_with_path(file: File, path: Option<PathBuf>) -> Self {
+        let len = file_len(&file);
+        let writer = BufWriter::new(file);
+
+        Self {
+            path,
+            capacity: writer.capacity(),
+            writer: Some(MaybeBuffered::Buffered(writer)),
+            colors: false,
+            was_empty: len == Some(0),
+            max_bytes: None,
+            len: len.unwrap_or(0),
+            at_line_start: true,
+        }
+    }
+
+    /// Truncate the file to zero bytes before writing a line that would cause it to grow beyond
+    /// `max_bytes`. The file can still temporarily grow beyond this limit by up to one record.
+    pub fn set_max_bytes(&mut self, max_bytes: u64) {
+        self.max_bytes = Some(max_bytes);
+    }
+
+    /// Discard the file's current contents. Buffered data is flushed first so it doesn't end up
+    /// in the truncated file.
+    fn truncate(&mut self) -> std::io::Result<()> {
+        let writer = self.writer()?;
+        writer.flush()?;
+        // The file is opened in append mode, so new writes will start at the beginning again
+        writer.get_ref().set_len(0)?;
+        self.len = 0;
+
+        Ok(())
+    }
+
+    /// Write a UTF-8 byte order mark if the file was empty when it was opened. This should only be
+    /// called before anything else is written to the file.
+    pub fn write_utf8_bom(&mut self) -> std::io::Result<()> {
+        if self.was_empty {
+            let writer = self.writer()?;
+            writer.write_all("\u{feff}".as_bytes())?;
+            writer.flush()?;
+        }
+
+        Ok(())
+    }
+
+    /// Stop buffering writes. Every write is then sent to the file immediately. This should only be
+    /// called before anything is written to the file.
+    pub fn unbuffered(self) -> Self {
+        self.with_buffer_capacity(0)
+    }
+
+    /// Change the size of the write buffer. A capacity of zero means that every write is sent to
+    /// the file immediately. This should only be called before anything is written to the file.
+    pub fn with_buffer_capacity(self, capacity: usize) -> Self {
+        let mut this = self;
+        if let Some(writer) = this.writer.take() {
+            this.writer = Some(MaybeBuffered::with_capacity(capacity, writer.into_inner()));
+        }
+        this.capacity = capacity;
+
+        this
+    }
+
+    /// The path the file was opened from, if the file was not passed to the logger as a handle.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Flush the file and open it again from the same path. This is useful when the file has been
+    /// moved by an external log rotation tool. The new file is created if it doesn't exist. File
+    /// handles without a path are only flushed.
+    pub fn reopen(&mut self) -> std::io::Result<()> {
+        if self.path.is_none() {
+            return self.flush();
+        }
+
+        self.close()?;
+        self.open()
+    }
+
+    /// Flush and close the file. The file is opened again from the same path the next time
+    /// something is written to it. Does nothing if the file is already closed. File handles
+    /// without a path cannot be opened again, so they're only flushed.
+    pub fn close(&mut self) -> std::io::Result<()> {
+        if self.path.is_none() {
+            return self.flush();
+        }
+
+        match self.writer.take() {
+            Some(mut writer) => writer.flush(),
+            None => Ok(()),
+        }
+    }
+
+    /// Open the file from `self.path`. The file is created if it doesn't exist.
+    fn open(&mut self) -> std::io::Result<()> {
+        let Some(path) = &self.path else {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                "the file handle has no path to reopen it from",
+            ));
+        };
+        let file = File::options().create(true).append(true).open(path)?;
+        let len = file_len(&file);
+        self.was_empty = len == Some(0);
+        self.len = len.unwrap_or(0);
+        self.at_line_start = true;
+        // This keeps the file unbuffered if it was unbuffered before
+        self.writer = Some(MaybeBuffered::with_capacity(self.capacity, file));
+
+        Ok(())
+    }
+
+    /// The writer for the file. Opens the file again if it has been closed.
+    fn writer(&mut self) -> std::io::Result<&mut MaybeBuffered<File>> {
+        if self.writer.is_none() {
+            self.open()?;
+        }
+
+        Ok(self.writer.as_mut().expect("The file was just opened"))
+    }
+
+    /// Enable or disable writing ANSI color escape sequences.
+    pub fn set_colors(&mut self, colors: bool) {
+        self.colors = colors;
+    }
+}
+
+/// A file's current size in bytes, if it can be determined.
+fn file_len(file: &File) -> Option<u64> {
+    file.metadata().map(|metadata| metadata.len()).ok()
+}
+
+impl Write for FileWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if let Some(max_bytes) = self.max_bytes {
+            if self.at_line_start && self.len > 0 && self.len + buf.len() as u64 > max_bytes {
+                self.truncate()?;
+            }
+        }
+
+        let bytes_written = self.writer()?.write(buf)?;
+        self.len += bytes_written as u64;
+        if bytes_written > 0 {
+            self.at_line_start = buf[bytes_written - 1] == b'\n';
+        }
+
+        Ok(bytes_written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match &mut self.writer {
+            Some(writer) => writer.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl WriteExt for FileWriter {
+    fn supports_color(&self) -> bool {
+        self.colors
+    }
+
+    fn writes_ansi(&self) -> bool {
+        self.colors
+    }
+
+    fn set_fg_color(&mut self, color: Color) -> std::io::Result<()> {
+        if self.colors {
+            Ansi::new(self.writer()?).set_color(ColorSpec::new().set_fg(Some(color)))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn reset_colors(&mut self) {
+        if self.colors {
+            if let Ok(writer) = self.writer() {
+                let _ = Ansi::new(writer).reset();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::target::tests::temp_log_path;
+    use crate::target::OutputTargetImpl;
+
+    #[test]
+    fn capped_file_truncated_at_line_start() {
+        let path = temp_log_path("capped");
+        std::fs::write(&path, "previous run\n").unwrap();
+        let mut target = OutputTargetImpl::new_capped_file_path(&path, 24, false)
+            .unwrap()
+            .with_buffer_capacity(0);
+
+        // The existing contents count towards the limit. The second line would exceed it, so the
+        // file is truncated first.
+        target.writer().write_all(b"first line\n").unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"previous run\nfirst line\n");
+        target.writer().write_all(b"second line\n").unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"second line\n");
+
+        // A line written in multiple parts is never cut in half, even if it exceeds the limit
+        target.writer().write_all(b"third ").unwrap();
+        target.writer().write_all(b"and longest line\n").unwrap();
+        assert_eq!(
+            std::fs::read(&path).unwrap(),
+            b"second line\nthird and longest line\n"
+        );
+        target.writer().write_all(b"fourth\n").unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"fourth\n");
+
+        drop(target);
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,117 @@
+//! The writer for gzip compressed log files.
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use termcolor::{Ansi, Color, ColorSpec, WriteColor};
+
+use super::WriteExt;
+
+/// A writer that compresses the log output before writing it to a file. Flushing the writer after
+/// every record would emit a gzip sync point for every line and ruin the compression ratio, so
+/// [`flush()`][Write::flush()] does nothing. Compressed output is instead only written to the
+/// file when the encoder's buffers fill up, when [`sync()`][Self::sync()] is called, and when the
+/// stream is finished.
+///
+/// Finishing the stream writes the gzip trailer. If anything is written after that, then a new
+/// gzip member is appended to the file. Decompressing the file yields the concatenation of all
+/// members.
+pub struct GzipFileWriter {
+    /// The path the file was opened from. Used to start a new gzip member after the stream has
+    /// been finished.
+    path: PathBuf,
+    /// The encoder for the current gzip member. This is `None` after the stream has been
+    /// finished.
+    encoder: Option<GzEncoder<BufWriter<File>>>,
+    /// Whether ANSI color escape sequences should be written to the file.
+    colors: bool,
+}
+
+impl GzipFileWriter {
+    pub fn new(file: File, path: PathBuf) -> Self {
+        Self {
+            path,
+            encoder: Some(GzEncoder::new(BufWriter::new(file), Compression::default())),
+            colors: false,
+        }
+    }
+
+    /// The path the file was opened from.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Enable or disable writing ANSI color escape sequences.
+    pub fn set_colors(&mut self, colors: bool) {
+        self.colors = colors;
+    }
+
+    /// Emit a gzip sync point and write all compressed output to the file. Everything written up
+    /// to this point can then be decompressed, even if the process crashes before the stream is
+    /// finished. Every sync point slightly worsens the compression ratio.
+    pub fn sync(&mut self) -> std::io::Result<()> {
+        match &mut self.encoder {
+            Some(encoder) => encoder.flush(),
+            None => Ok(()),
+        }
+    }
+
+    /// Finish the current gzip member by writing the gzip trailer and closing the file. A new
+    /// member is started if anything is written after this. Does nothing if the stream has
+    /// already been finished.
+    pub fn finish(&mut self) -> std::io::Result<()> {
+        if let Some(encoder) = self.encoder.take() {
+            encoder.finish()?.flush()?;
+        }
+
+        Ok(())
+    }
+
+    /// The encoder for the current gzip member. Reopens the file and starts a new member if the
+    /// stream has been finished.
+    fn encoder(&mut self) -> std::io::Result<&mut GzEncoder<BufWriter<File>>> {
+        if self.encoder.is_none() {
+            let file = File::options().create(true).append(true).open(&self.path)?;
+            self.encoder = Some(GzEncoder::new(BufWriter::new(file), Compression::default()));
+        }
+
+        Ok(self.encoder.as_mut().expect("The encoder was just created"))
+    }
+}
+
+impl Write for GzipFileWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.encoder()?.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        // The logger flushes after every record. See the struct's docstring.
+        Ok(())
+    }
+}
+
+impl WriteExt for GzipFileWriter {
+    fn supports_color(&self) -> bool {
+        self.colors
+    }
+
+    fn writes_ansi(&self) -> bool {
+        self.colors
+    }
+
+    fn set_fg_color(&mut self, color: Color) -> std::io::Result<()> {
+        if self.colors {
+            Ansi::new(self).set_color(ColorSpec::new().set_fg(Some(color)))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn reset_colors(&mut self) {
+        if self.colors {
+            let _ = Ansi::new(self).reset();
+        }
+    }
+}
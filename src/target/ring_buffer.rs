@@ -0,0 +1,108 @@
+//! An in-memory output target that only keeps the most recent output.
+
+use std::io::Write;
+
+/// A fixed size circular buffer. When the buffer is full, new writes overwrite the oldest data.
+/// The buffer is allocated once up front and is never reallocated, so it always ends up in core
+/// dumps and it can be read from a crash handler.
+pub struct RingBufferWriter {
+    buffer: Box<[u8]>,
+    /// The position the next byte is written to.
+    position: usize,
+    /// Whether the buffer has wrapped around at least once. If it has, then the oldest data starts
+    /// at `position`.
+    wrapped: bool,
+}
+
+impl RingBufferWriter {
+    pub fn new(size: usize) -> Self {
+        Self {
+            buffer: vec![0; size].into_boxed_slice(),
+            position: 0,
+            wrapped: false,
+        }
+    }
+
+    /// The buffer's size in bytes.
+    pub fn size(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// The buffer's contents, from oldest to newest. If the buffer has wrapped around, then the
+    /// first line is likely incomplete.
+    pub fn contents(&self) -> Vec<u8> {
+        if self.wrapped {
+            let (newest, oldest) = self.buffer.split_at(self.position);
+            [oldest, newest].concat()
+        } else {
+            self.buffer[..self.position].to_vec()
+        }
+    }
+}
+
+impl Write for RingBufferWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let size = self.buffer.len();
+        if size == 0 {
+            return Ok(buf.len());
+        }
+
+        // Only the last `size` bytes would survive anyways
+        let mut remaining = &buf[buf.len().saturating_sub(size)..];
+        while !remaining.is_empty() {
+            let chunk_len = remaining.len().min(size - self.position);
+            self.buffer[self.position..self.position + chunk_len]
+                .copy_from_slice(&remaining[..chunk_len]);
+            remaining = &remaining[chunk_len..];
+
+            self.position += chunk_len;
+            if self.position == size {
+                self.position = 0;
+                self.wrapped = true;
+            }
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn contents_before_wrapping() {
+        let mut buffer = RingBufferWriter::new(8);
+        assert_eq!(buffer.contents(), b"");
+        buffer.write_all(b"abc").unwrap();
+        buffer.write_all(b"defgh").unwrap();
+        assert_eq!(buffer.contents(), b"abcdefgh");
+    }
+
+    #[test]
+    fn wraparound_keeps_newest_bytes() {
+        let mut buffer = RingBufferWriter::new(8);
+        buffer.write_all(b"abcdef").unwrap();
+        buffer.write_all(b"ghij").unwrap();
+        assert_eq!(buffer.contents(), b"cdefghij");
+
+        buffer.write_all(b"k").unwrap();
+        assert_eq!(buffer.contents(), b"defghijk");
+
+        // Writes larger than the buffer only keep their last bytes
+        buffer.write_all(b"0123456789").unwrap();
+        assert_eq!(buffer.contents(), b"23456789");
+        assert_eq!(buffer.size(), 8);
+    }
+
+    #[test]
+    fn zero_size_discards_everything() {
+        let mut buffer = RingBufferWriter::new(0);
+        buffer.write_all(b"abc").unwrap();
+        assert_eq!(buffer.contents(), b"");
+    }
+}
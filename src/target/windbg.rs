@@ -0,0 +1,384 @@
+//! Adapters for logging to a windows debugger. Split off into a module to avoid littering `#[cfg]`
+//! attributes all over the place.
+
+use std::io::Write;
+
+#[cfg(windows)]
+use windows::core::PCWSTR;
+#[cfg(windows)]
+use windows::Win32::System::Diagnostics::Debug::{IsDebuggerPresent, OutputDebugStringW};
+
+/// The soft limit for the buffer's size. When a line gets longer than this, then it is flushed as
+/// a partial line to prevent the buffer from growing indefinitely.
+///
+/// This is the default capacity used for `BufWriter`:
+/// <https://github.com/rust-lang/rust/blob/5423745db8b434fcde54888b35f518f00cce00e4/library/std/src/sys_common/io.rs#L1-L3>
+const MAX_BUFFER_LEN: usize = 8 * 1024;
+
+/// A shim to provide a writes `write!()` implementation that writes to the Windows debugger using
+/// `OutputDebugStringW()`. Provides line-based buffering since `OutputDebugString` normally
+/// immediately flushes. Since this needs to convert the bytes input from UTF-8 to UTF-16, this is
+/// not going to be particularly efficient.
+///
+/// # Notes
+///
+/// This provides a general [`Write`] interface, but this only supports writing UTF-8 text. Invalid
+/// UTF-8 sequences are replaced with U+FFFD, like in [`String::from_utf8_lossy()`].
+pub struct WinDbgWriter {
+    /// Unwritten output. Will be flushed either when `flush` is called, when a carriege return is
+    /// printed, or when the buffer would grow beyond [`MAX_BUFFER_LEN`]. An incomplete UTF-8
+    /// sequence at the end of the buffer is kept in the buffer when flushing.
+    buffer: Vec<u8>,
+    /// An intermediary buffer used to convert UTF-8 text from `buffer` into UTF-16 so it can be
+    /// output using `OutputDebugStringW()`. `OutputDebugStringA()` can be used with UTF-8 text, but
+    /// only in very recent Windows versions.
+    utf16_buffer: Vec<u16>,
+    /// Sends a null terminated UTF-16 string to the debugger. This is
+    /// [`output_debug_string()`] by default, but it can be replaced to capture the output
+    /// instead.
+    output_fn: fn(&[u16]),
+    /// Checks whether a debugger is attached. This is [`debugger_present()`] by default.
+    attached_fn: fn() -> bool,
+    /// Whether ANSI color escape sequences should be written to the debugger. This is decided
+    /// separately from the STDERR stream's colors since a debugger is never a TTY.
+    colors: bool,
+}
+
+impl std::fmt::Debug for WinDbgWriter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WinDbgWriter")
+            .field("buffer", &self.buffer)
+            .field("utf16_buffer", &self.utf16_buffer)
+            .field("colors", &self.colors)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(windows)]
+impl Default for WinDbgWriter {
+    fn default() -> Self {
+        Self::with_output_fns(output_debug_string, debugger_present)
+    }
+}
+
+impl Drop for WinDbgWriter {
+    fn drop(&mut self) {
+        // Make sure to write any remaining partial lines to the debugger console when the object is
+        // dropped
+        let _ = self.flush();
+
+        // At this point the buffer can only contain an incomplete UTF-8 sequence that will never be
+        // completed
+        if !self.buffer.is_empty() {
+            self.utf16_buffer.clear();
+            self.utf16_buffer
+                .extend(String::from_utf8_lossy(&self.buffer).encode_utf16());
+            self.utf16_buffer.push(0);
+            (self.output_fn)(&self.utf16_buffer);
+        }
+    }
+}
+
+impl WinDbgWriter {
+    /// Create a writer that sends its output to `output_fn` instead of to `OutputDebugStringW()`,
+    /// and that uses `attached_fn` to check whether a debugger is attached instead of
+    /// `IsDebuggerPresent()`. `output_fn` receives a null terminated UTF-16 string for every
+    /// flush. This makes it possible to exercise the buffering and the UTF-16 conversion without a
+    /// debugger.
+    pub fn with_output_fns(output_fn: fn(&[u16]), attached_fn: fn() -> bool) -> Self {
+        Self {
+            buffer: Vec::with_capacity(MAX_BUFFER_LEN),
+            utf16_buffer: Vec::with_capacity(MAX_BUFFER_LEN),
+            output_fn,
+            attached_fn,
+            colors: false,
+        }
+    }
+
+    /// Whether ANSI color escape sequences should be written to the debugger.
+    pub fn colors(&self) -> bool {
+        self.colors
+    }
+
+    /// Enable or disable writing ANSI color escape sequences. Not every debugger can display these,
+    /// so this is disabled by default.
+    pub fn set_colors(&mut self, colors: bool) {
+        self.colors = colors;
+    }
+
+    /// Whether a debugger is currently attached, according to this writer's `attached_fn`.
+    pub fn attached(&self) -> bool {
+        (self.attached_fn)()
+    }
+
+    /// Append data to the buffer. Extremely long lines are written in parts so the buffer doesn't
+    /// grow without bounds. `OutputDebugString()` similarly splits up long strings.
+    fn extend_buffer(&mut self, buf: &[u8]) -> std::io::Result<()> {
+        for chunk in buf.chunks(MAX_BUFFER_LEN) {
+            if self.buffer.len() + chunk.len() > MAX_BUFFER_LEN {
+                self.flush()?;
+            }
+            self.buffer.extend_from_slice(chunk);
+        }
+
+        Ok(())
+    }
+}
+
+impl Write for WinDbgWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        const LINE_FEED: u8 = b'\n';
+
+        // We'll buffer writes to only flush on newlines because `IsDebuggerPresent()` is unbuffered
+        // and the way the logs are written assumes buffered writes. If `buf` contains multiple
+        // lines, then all complete lines are flushed at once and only the trailing partial line
+        // is kept in the buffer.
+        match buf.iter().rposition(|c| c == &LINE_FEED) {
+            Some(last_line_feed_idx) => {
+                let (complete_lines, partial_line) = buf.split_at(last_line_feed_idx + 1);
+                self.extend_buffer(complete_lines)?;
+                self.flush()?;
+                self.extend_buffer(partial_line)?;
+            }
+            None => self.extend_buffer(buf)?,
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+
+        // As explained above, we'll use `OutputDebugStringW()` instead of `OutputDebugStringA()` to
+        // better support legacy platforms. This requires us to convert the UTF-8 buffer into UTF-16
+        // first.
+        self.utf16_buffer.clear();
+        let mut remaining = self.buffer.as_slice();
+        loop {
+            match std::str::from_utf8(remaining) {
+                Ok(remaining_str) => {
+                    self.utf16_buffer.extend(remaining_str.encode_utf16());
+                    remaining = &[];
+                    break;
+                }
+                Err(err) => {
+                    let (valid, rest) = remaining.split_at(err.valid_up_to());
+                    let valid_str = std::str::from_utf8(valid).unwrap_or_default();
+                    self.utf16_buffer.extend(valid_str.encode_utf16());
+                    match err.error_len() {
+                        // Invalid sequences are replaced like in `String::from_utf8_lossy()` so
+                        // the rest of the line is still written
+                        Some(invalid_len) => {
+                            // U+FFFD is in the basic multilingual plane, so it's a single code unit
+                            self.utf16_buffer.push(char::REPLACEMENT_CHARACTER as u16);
+                            remaining = &rest[invalid_len..];
+                        }
+                        // A code point may be split over multiple writes, or over two chunks in
+                        // `extend_buffer()`. In that case the buffer ends with an incomplete
+                        // sequence, which is kept in the buffer until the rest of the code point
+                        // has been written.
+                        None => {
+                            remaining = rest;
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+        let decoded_len = self.buffer.len() - remaining.len();
+        self.buffer.drain(..decoded_len);
+        if self.utf16_buffer.is_empty() {
+            return Ok(());
+        }
+
+        // The UTF-16 buffer is treated as a null terminated string
+        self.utf16_buffer.push(0);
+        (self.output_fn)(&self.utf16_buffer);
+
+        Ok(())
+    }
+}
+
+/// Send a null terminated UTF-16 string to the Windows debugger.
+#[cfg(windows)]
+pub fn output_debug_string(string: &[u16]) {
+    debug_assert_eq!(string.last(), Some(&0));
+
+    unsafe { OutputDebugStringW(PCWSTR::from_raw(string.as_ptr())) };
+}
+
+/// Whether the windows debugger is currently attached.
+#[cfg(windows)]
+pub fn debugger_present() -> bool {
+    unsafe { IsDebuggerPresent().as_bool() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    thread_local! {
+        /// Everything passed to `capture_output()` on this thread, without the null terminators.
+        static OUTPUT: RefCell<Vec<Vec<u16>>> = const { RefCell::new(Vec::new()) };
+    }
+
+    fn capture_output(string: &[u16]) {
+        assert_eq!(string.last(), Some(&0), "The string is not null terminated");
+        OUTPUT.with(|output| {
+            output
+                .borrow_mut()
+                .push(string[..string.len() - 1].to_vec())
+        });
+    }
+
+    /// Take the strings captured so far, decoded from UTF-16.
+    fn take_output() -> Vec<String> {
+        OUTPUT.with(|output| {
+            output
+                .take()
+                .iter()
+                .map(|string| String::from_utf16(string).expect("Invalid UTF-16"))
+                .collect()
+        })
+    }
+
+    fn test_writer() -> WinDbgWriter {
+        WinDbgWriter::with_output_fns(capture_output, || true)
+    }
+
+    #[test]
+    fn complete_lines_flushed_at_once() {
+        let mut writer = test_writer();
+        writer.write_all(b"first\nsecond\n").unwrap();
+
+        assert_eq!(take_output(), ["first\nsecond\n"]);
+    }
+
+    #[test]
+    fn partial_lines_held_until_line_feed() {
+        let mut writer = test_writer();
+        writer.write_all(b"first\npartial").unwrap();
+        assert_eq!(take_output(), ["first\n"]);
+
+        writer.write_all(b" line").unwrap();
+        assert!(take_output().is_empty());
+
+        writer.write_all(b"\n").unwrap();
+        assert_eq!(take_output(), ["partial line\n"]);
+    }
+
+    #[test]
+    fn partial_lines_written_on_flush() {
+        let mut writer = test_writer();
+        writer.write_all(b"partial").unwrap();
+        assert!(take_output().is_empty());
+
+        writer.flush().unwrap();
+        assert_eq!(take_output(), ["partial"]);
+
+        // Flushing an empty buffer does not output anything
+        writer.flush().unwrap();
+        assert!(take_output().is_empty());
+    }
+
+    #[test]
+    fn partial_lines_written_on_drop() {
+        let mut writer = test_writer();
+        writer.write_all(b"partial").unwrap();
+        drop(writer);
+
+        assert_eq!(take_output(), ["partial"]);
+    }
+
+    #[test]
+    fn long_lines_split() {
+        let mut writer = test_writer();
+        let line = "a".repeat(MAX_BUFFER_LEN * 2 + 10);
+        writer.write_all(line.as_bytes()).unwrap();
+        writer.flush().unwrap();
+
+        let output = take_output();
+        assert_eq!(output.len(), 3);
+        assert!(output.iter().all(|part| part.len() <= MAX_BUFFER_LEN));
+        assert_eq!(output.concat(), line);
+    }
+
+    #[test]
+    fn utf16_conversion() {
+        let mut writer = test_writer();
+        writer.write_all("héllo 🦀\n".as_bytes()).unwrap();
+
+        let output = OUTPUT.with(|output| output.take());
+        assert_eq!(output, ["héllo 🦀\n".encode_utf16().collect::<Vec<_>>()]);
+    }
+
+    #[test]
+    fn code_point_split_over_writes() {
+        let mut writer = test_writer();
+        let [first_byte, second_byte] = "é".as_bytes() else {
+            unreachable!()
+        };
+
+        // The incomplete sequence is kept in the buffer instead of being written
+        writer.write_all(&[*first_byte]).unwrap();
+        writer.flush().unwrap();
+        assert!(OUTPUT.with(|output| output.take()).is_empty());
+
+        writer.write_all(&[*second_byte]).unwrap();
+        writer.flush().unwrap();
+        assert_eq!(OUTPUT.with(|output| output.take()), [vec![0xe9]]);
+    }
+
+    #[test]
+    fn code_point_split_over_chunks() {
+        // The first byte of the `é` ends up at the end of the first chunk in `extend_buffer()`
+        let mut writer = test_writer();
+        let line = format!("{}é", "a".repeat(MAX_BUFFER_LEN - 1));
+        writer.write_all(line.as_bytes()).unwrap();
+        writer.flush().unwrap();
+
+        assert_eq!(
+            take_output(),
+            ["a".repeat(MAX_BUFFER_LEN - 1), String::from("é")]
+        );
+    }
+
+    #[test]
+    fn incomplete_code_point_written_on_drop() {
+        let mut writer = test_writer();
+        writer.write_all(b"abc\xc3").unwrap();
+        drop(writer);
+
+        assert_eq!(take_output(), ["abc", "\u{fffd}"]);
+    }
+
+    #[test]
+    fn invalid_utf8_replaced() {
+        let mut writer = test_writer();
+        writer
+            .write_all(b"valid \xff still \xe2\x82 written\n")
+            .unwrap();
+
+        assert_eq!(take_output(), ["valid \u{fffd} still \u{fffd} written\n"]);
+    }
+
+    #[test]
+    fn invalid_utf8_before_incomplete_code_point() {
+        let mut writer = test_writer();
+        writer.write_all(b"a\xffb\xc3").unwrap();
+        writer.flush().unwrap();
+        assert_eq!(take_output(), ["a\u{fffd}b"]);
+
+        writer.write_all(b"\xa9\n").unwrap();
+        assert_eq!(take_output(), ["é\n"]);
+    }
+
+    #[test]
+    fn attached_overridden() {
+        assert!(WinDbgWriter::with_output_fns(capture_output, || true).attached());
+        assert!(!WinDbgWriter::with_output_fns(capture_output, || false).attached());
+    }
+}
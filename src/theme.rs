@@ -0,0 +1,88 @@
+//! Color themes for the logger's output.
+
+use log::Level;
+pub use termcolor::Color;
+
+/// The colors used for the different parts of a log line when the output target supports colors.
+/// A `None` means the part uses the terminal's default foreground color. See
+/// [`LoggerBuilder::with_theme()`][crate::LoggerBuilder::with_theme()].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Theme {
+    /// The color for the `[ERROR]` tag.
+    pub error: Option<Color>,
+    /// The color for the `[WARN]` tag.
+    pub warn: Option<Color>,
+    /// The color for the `[INFO]` tag.
+    pub info: Option<Color>,
+    /// The color for the `[DEBUG]` tag.
+    pub debug: Option<Color>,
+    /// The color for the `[TRACE]` tag.
+    pub trace: Option<Color>,
+    /// The color for the timestamp at the start of the line.
+    pub timestamp: Option<Color>,
+    /// The color for the module path and target.
+    pub module_path: Option<Color>,
+    /// The color for the source location shown for trace messages.
+    pub location: Option<Color>,
+}
+
+impl Default for Theme {
+    /// The default theme. Only the level tags are colored. The `[TRACE]` tag uses bright black,
+    /// which most terminals show as gray, so trace messages are easy to tell apart from debug
+    /// messages.
+    fn default() -> Self {
+        Self {
+            error: Some(Color::Red),
+            warn: Some(Color::Yellow),
+            info: Some(Color::Blue),
+            debug: Some(Color::Cyan),
+            trace: Some(Color::Ansi256(8)),
+            timestamp: None,
+            module_path: None,
+            location: None,
+        }
+    }
+}
+
+impl Theme {
+    /// A theme with more distinct colors that are easier to tell apart on dark backgrounds. The
+    /// timestamp, module path, and source location are also colored.
+    pub fn high_contrast() -> Self {
+        Self {
+            error: Some(Color::Red),
+            warn: Some(Color::Yellow),
+            info: Some(Color::Green),
+            debug: Some(Color::Cyan),
+            trace: Some(Color::Magenta),
+            timestamp: Some(Color::White),
+            module_path: Some(Color::Cyan),
+            location: Some(Color::Magenta),
+        }
+    }
+
+    /// A theme without any colors. Unlike [`ColorMode::Never`][crate::ColorMode::Never], escape
+    /// sequences for clickable locations are still written to terminals that support them.
+    pub fn monochrome() -> Self {
+        Self {
+            error: None,
+            warn: None,
+            info: None,
+            debug: None,
+            trace: None,
+            timestamp: None,
+            module_path: None,
+            location: None,
+        }
+    }
+
+    /// The color for a log level's tag.
+    pub fn level_color(&self, level: Level) -> Option<Color> {
+        match level {
+            Level::Error => self.error,
+            Level::Warn => self.warn,
+            Level::Info => self.info,
+            Level::Debug => self.debug,
+            Level::Trace => self.trace,
+        }
+    }
+}
@@ -0,0 +1,86 @@
+//! Per call site throttling for individual log statements. See [`throttled!()`][crate::throttled!()].
+
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// A throttled call site's file name, line number, and column.
+type CallSite = (&'static str, u32, u32);
+
+/// When each throttled call site last logged.
+static LAST_EMITTED: Lazy<Mutex<HashMap<CallSite, Instant>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Log a message, but at most once per `interval` for this call site. Repeats within the interval
+/// are silently dropped. Call sites are identified by their file name, line number, and column, so
+/// every `throttled!()` statement is throttled independently, even when there are several on the
+/// same line. Statements generated by a single invocation of another macro share that
+/// invocation's location, and thus also its throttling. This is useful for noisy log statements,
+/// for instance in a loop, when filtering out the entire module would hide too much.
+///
+/// The first time a call site is reached, its timestamp is inserted into a global map, which may
+/// allocate. Code that's checked with `assert_no_alloc` should reach every throttled call site once
+/// outside of the realtime context, or use regular log statements instead.
+///
+/// The level and message are passed to [`log::log!()`], so they accept the same arguments. For
+/// instance, `nih_log::throttled!(Duration::from_secs(1), Level::Warn, "buffer {i} underran")`
+/// logs the underrun at most once per second.
+#[macro_export]
+macro_rules! throttled {
+    ($interval:expr, $level:expr, $($arg:tt)+) => {{
+        let level = $level;
+        if $crate::__private::log::log_enabled!(level)
+            && $crate::__private::throttle_call_site(
+                ::std::file!(),
+                ::std::line!(),
+                ::std::column!(),
+                $interval,
+            )
+        {
+            $crate::__private::log::log!(level, $($arg)+);
+        }
+    }};
+}
+
+/// Whether a throttled call site should log now. If it does, then the call site's timestamp is
+/// updated. Used by [`throttled!()`][crate::throttled!()].
+pub fn throttle_call_site(file: &'static str, line: u32, column: u32, interval: Duration) -> bool {
+    let now = Instant::now();
+    // The map only contains timestamps, so it's fine to keep using it if another thread panicked
+    let mut last_emitted = match LAST_EMITTED.lock() {
+        Ok(last_emitted) => last_emitted,
+        Err(err) => err.into_inner(),
+    };
+    match last_emitted.get_mut(&(file, line, column)) {
+        Some(last) if now.duration_since(*last) < interval => false,
+        Some(last) => {
+            *last = now;
+            true
+        }
+        None => {
+            last_emitted.insert((file, line, column), now);
+            true
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn call_sites_on_the_same_line_are_independent() {
+        let interval = Duration::from_secs(3600);
+        let line = line!();
+        let call_sites = || {
+            (
+                throttle_call_site(file!(), line, 10, interval),
+                throttle_call_site(file!(), line, 20, interval),
+            )
+        };
+
+        assert_eq!(call_sites(), (true, true));
+        assert_eq!(call_sites(), (false, false));
+    }
+}
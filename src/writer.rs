@@ -0,0 +1,69 @@
+//! A [`Write`] adapter that turns written lines into log records.
+
+use log::Level;
+use std::io::Write;
+
+/// Create a writer that emits every line written to it as a log record at the given level. This
+/// can be used to capture the output of libraries that only write to a [`Write`] implementation.
+/// Partial lines are buffered until a newline is written, or until the writer is flushed or
+/// dropped.
+pub fn writer_for(level: Level) -> LogWriter {
+    LogWriter {
+        level,
+        buffer: Vec::new(),
+    }
+}
+
+/// A writer that emits every line written to it as a log record. Created using [`writer_for()`].
+#[derive(Debug)]
+pub struct LogWriter {
+    /// The level the log records are emitted at.
+    level: Level,
+    /// Unwritten output. Will be emitted as a log record either when `flush` is called, or when a
+    /// newline is written.
+    buffer: Vec<u8>,
+}
+
+impl LogWriter {
+    /// Emit the buffered line as a log record, if there is one.
+    fn emit_line(&mut self) {
+        if self.buffer.is_empty() {
+            return;
+        }
+
+        let line = String::from_utf8_lossy(&self.buffer);
+        let line = line.strip_suffix('\n').unwrap_or(&line);
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        log::log!(self.level, "{line}");
+
+        self.buffer.clear();
+    }
+}
+
+impl Drop for LogWriter {
+    fn drop(&mut self) {
+        // Make sure any partial lines are still logged
+        self.emit_line();
+    }
+}
+
+impl Write for LogWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        const LINE_FEED: u8 = b'\n';
+
+        for line in buf.split_inclusive(|c| c == &LINE_FEED) {
+            self.buffer.extend_from_slice(line);
+            if line.last() == Some(&LINE_FEED) {
+                self.emit_line();
+            }
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.emit_line();
+
+        Ok(())
+    }
+}